@@ -30,7 +30,7 @@ use crate::utilities::{
 };
 
 
-pub trait Action {
+pub trait Action: Send + Sync {
     fn is_legal(&self, state: &GameState) -> bool;
     fn apply(&self, state: &GameState) -> GameState;
     fn name(&self) -> &str;
@@ -56,6 +56,8 @@ pub struct Castle {
 
 #[derive(Debug)]
 #[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
 pub struct Promotion {
     pub pawn_becomes: PieceName,
     pub moving_from: usize,
@@ -64,6 +66,8 @@ pub struct Promotion {
 
 #[derive(Debug)]
 #[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
 pub struct Move {
     pub from: usize,
     pub to: usize,
@@ -71,6 +75,8 @@ pub struct Move {
 
 #[derive(Debug)]
 #[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
 pub struct Capture {
     pub on: usize,
     pub with: usize,
@@ -78,10 +84,53 @@ pub struct Capture {
 
 #[derive(Debug)]
 #[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
 pub struct EnPassant {
     pub with: usize,
 }
 
+// Any one of the action types, as a plain value that can be copied,
+// compared and stored, unlike a Box<dyn Action>
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum ChessMove {
+    Move(Move),
+    Capture(Capture),
+    EnPassant(EnPassant),
+    Castle(Castle),
+    Promotion(Promotion),
+}
+
+impl ChessMove {
+    fn action(&self) -> &dyn Action {
+        match self {
+            ChessMove::Move(action) => action,
+            ChessMove::Capture(action) => action,
+            ChessMove::EnPassant(action) => action,
+            ChessMove::Castle(action) => action,
+            ChessMove::Promotion(action) => action,
+        }
+    }
+}
+
+impl Action for ChessMove {
+    fn name(&self) -> &str {
+        self.action().name()
+    }
+    fn as_algebraic_notation(&self, state: &GameState) -> String {
+        self.action().as_algebraic_notation(state)
+    }
+    fn is_legal(&self, state: &GameState) -> bool {
+        self.action().is_legal(state)
+    }
+    fn apply(&self, state: &GameState) -> GameState {
+        self.action().apply(state)
+    }
+}
+
 impl Action for Move {
     fn name(&self) -> &str {
         "Move"
@@ -137,6 +186,16 @@ impl Action for Move {
         if !move_is_pseudo_legal(self.from, self.to, &state) {
             return false
         }
+        // Pawns moving diagonally or onto the last rank are an EnPassant
+        // or a Promotion instead
+        if state.squares[self.from].unwrap().name == Pawn {
+            if !movement_is_vertical(self.from, self.to) {
+                return false
+            }
+            if self.to < 8 || self.to > 55 {
+                return false
+            }
+        }
         // Don't allow moves that leave the current player checked
         if color_is_checked(state.to_move, &self.apply(&state)) {
             return false
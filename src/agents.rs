@@ -0,0 +1,282 @@
+// The single responsibility of this module is to provide simple players to
+// compare learned policies against, and a way to play them against each
+// other.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::Color::{White, Black};
+
+use crate::actions::{
+    Action,
+    ChessMove,
+};
+
+use crate::utilities::{
+    is_checkmate,
+    legal_chess_moves,
+    relative_material_values,
+};
+
+use crate::hashing::next_random;
+
+use crate::arena::{
+    Game,
+    Match,
+    MoveRequest,
+    Player,
+    PlayerError,
+    TimeControl,
+};
+
+// Scores beyond any material balance, for positions that are mated
+const MATE_VALUE: i32 = 1_000;
+
+// Something that chooses moves. Agents are only asked to move in
+// positions that have a legal move.
+pub trait Agent {
+    fn select_action(&mut self, state: &GameState) -> ChessMove;
+
+    fn name(&self) -> String {
+        "agent".to_string()
+    }
+}
+
+impl<A: Agent + ?Sized> Agent for &mut A {
+    fn select_action(&mut self, state: &GameState) -> ChessMove {
+        (**self).select_action(state)
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+}
+
+impl<A: Agent + ?Sized> Agent for Box<A> {
+    fn select_action(&mut self, state: &GameState) -> ChessMove {
+        (**self).select_action(state)
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+}
+
+// Lets an agent take part in a match, against UCI engines for example.
+// Agents ignore the clock.
+pub struct AgentPlayer<A: Agent> {
+    agent: A,
+}
+
+impl<A: Agent> AgentPlayer<A> {
+    pub fn new(agent: A) -> AgentPlayer<A> {
+        AgentPlayer { agent }
+    }
+}
+
+impl<A: Agent> Player for AgentPlayer<A> {
+    fn name(&self) -> String {
+        self.agent.name()
+    }
+
+    fn select_move(&mut self, request: &MoveRequest) -> Result<ChessMove, PlayerError> {
+        if legal_chess_moves(request.state).is_empty() {
+            return Err(PlayerError::NoMove);
+        }
+        Ok(self.agent.select_action(request.state))
+    }
+}
+
+// Play one game from the initial position, ending it by the same rules
+// as a match
+pub fn play_game(white: &mut dyn Agent, black: &mut dyn Agent) -> Game {
+    let mut white = AgentPlayer::new(white);
+    let mut black = AgentPlayer::new(black);
+    // The time control is never looked at
+    let mut result = Match::new(1, TimeControl::Depth(1)).run(&mut white, &mut black);
+    result.games.remove(0)
+}
+
+// A seeded source of random numbers, so that games can be replayed
+pub(crate) struct Random {
+    seed: u64,
+}
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Random {
+        Random { seed }
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        let (seed, random) = next_random(self.seed);
+        self.seed = seed;
+        random
+    }
+
+    // A number from 0 up to but not including `n`
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    // A number from 0 up to but not including 1
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Plays any legal move, uniformly at random
+pub struct RandomAgent {
+    random: Random,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64) -> RandomAgent {
+        RandomAgent { random: Random::new(seed) }
+    }
+}
+
+impl Agent for RandomAgent {
+    fn select_action(&mut self, state: &GameState) -> ChessMove {
+        let moves = legal_chess_moves(state);
+        moves[self.random.below(moves.len())]
+    }
+
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+}
+
+// Plays the move leaving it furthest ahead in material, choosing at random
+// between equally good moves
+pub struct GreedyMaterialAgent {
+    random: Random,
+}
+
+impl GreedyMaterialAgent {
+    pub fn new(seed: u64) -> GreedyMaterialAgent {
+        GreedyMaterialAgent { random: Random::new(seed) }
+    }
+}
+
+impl Agent for GreedyMaterialAgent {
+    fn select_action(&mut self, state: &GameState) -> ChessMove {
+        let scored: Vec<(i32, ChessMove)> = legal_chess_moves(state)
+            .into_iter()
+            .map(|action| (-material_balance(&action.apply(state)), action))
+            .collect();
+        let best = scored.iter().map(|(score, _)| *score).max().unwrap();
+        let best_moves: Vec<ChessMove> = scored
+            .into_iter()
+            .filter(|(score, _)| *score == best)
+            .map(|(_, action)| action)
+            .collect();
+        best_moves[self.random.below(best_moves.len())]
+    }
+
+    fn name(&self) -> String {
+        "greedy material".to_string()
+    }
+}
+
+// Captures whenever it can, without looking at what it captures or what
+// happens next, and otherwise plays at random
+pub struct CaptureAgent {
+    random: Random,
+}
+
+impl CaptureAgent {
+    pub fn new(seed: u64) -> CaptureAgent {
+        CaptureAgent { random: Random::new(seed) }
+    }
+}
+
+impl Agent for CaptureAgent {
+    fn select_action(&mut self, state: &GameState) -> ChessMove {
+        let moves = legal_chess_moves(state);
+        let captures: Vec<ChessMove> = moves
+            .iter()
+            .filter(|action| is_capture(state, action))
+            .copied()
+            .collect();
+        if captures.is_empty() {
+            moves[self.random.below(moves.len())]
+        } else {
+            captures[self.random.below(captures.len())]
+        }
+    }
+
+    fn name(&self) -> String {
+        "capture".to_string()
+    }
+}
+
+// Searches every line `depth` plies deep and plays the move with the best
+// material balance at the end, assuming best play by both sides. Alpha
+// beta pruning skips lines that can't matter, which doesn't change the
+// move chosen.
+pub struct MinimaxAgent {
+    depth: usize,
+}
+
+impl MinimaxAgent {
+    pub fn new(depth: usize) -> MinimaxAgent {
+        MinimaxAgent { depth: depth.max(1) }
+    }
+}
+
+impl Agent for MinimaxAgent {
+    fn select_action(&mut self, state: &GameState) -> ChessMove {
+        let moves = legal_chess_moves(state);
+        let mut best_move = moves[0];
+        let mut alpha = -MATE_VALUE - 1;
+        for action in moves {
+            let score = -minimax(&action.apply(state), self.depth - 1, 1, -MATE_VALUE - 1, -alpha);
+            if score > alpha {
+                alpha = score;
+                best_move = action;
+            }
+        }
+        best_move
+    }
+
+    fn name(&self) -> String {
+        format!("minimax depth {}", self.depth)
+    }
+}
+
+// The score of a position for the side to move, in pawns
+fn minimax(state: &GameState, depth: usize, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+    let moves = legal_chess_moves(state);
+    if moves.is_empty() {
+        // Mating sooner is better
+        return if is_checkmate(state) { -MATE_VALUE + ply } else { 0 };
+    }
+    if depth == 0 {
+        return material_balance(state);
+    }
+    for action in moves {
+        let score = -minimax(&action.apply(state), depth - 1, ply + 1, -beta, -alpha);
+        if score >= beta {
+            return score;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+// Material for the side to move, less material for the other side
+fn material_balance(state: &GameState) -> i32 {
+    let (white, black) = relative_material_values(state);
+    let balance = white as i32 - black as i32;
+    match state.to_move {
+        White => balance,
+        Black => -balance,
+    }
+}
+
+fn is_capture(state: &GameState, action: &ChessMove) -> bool {
+    match action {
+        ChessMove::Capture(_) | ChessMove::EnPassant(_) => true,
+        ChessMove::Promotion(promotion) => state.squares[promotion.to].is_some(),
+        _ => false,
+    }
+}
@@ -0,0 +1,1016 @@
+// The single responsibility of this module is to play matches between two
+// players, whether in-process searches or UCI engines running as child
+// processes, and to record the games they play.
+
+use crate::gamestate::{
+    GameState,
+    FenError,
+};
+
+use crate::pieces::{
+    PieceName,
+    Color::{White, Black},
+};
+
+use crate::actions::{
+    Action,
+    ChessMove,
+};
+
+use crate::utilities::{
+    is_checkmate,
+    is_stalemate,
+    color_is_checked,
+};
+
+use crate::notation::{
+    fen_notation,
+    to_uci,
+    parse_uci_move,
+    parse_san,
+};
+
+use crate::search::{
+    Search,
+    SearchLimits,
+};
+
+use crate::transposition::{
+    TranspositionTable,
+    DEFAULT_TABLE_MEGABYTES,
+};
+
+use crate::evaluation::{
+    Evaluator,
+    ClassicalEvaluator,
+};
+
+use crate::statistics::{
+    EloEstimate,
+    Sprt,
+    SprtDecision,
+    elo_from_wdl,
+};
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// Games are drawn after this many plies unless set otherwise
+pub const DEFAULT_MAX_PLIES: usize = 400;
+
+// How long a UCI engine may take to start, or to answer isready
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How much longer than its remaining time a UCI engine is given to reply,
+// so that pipe latency doesn't lose games. Overstepping the clock itself
+// is still a time forfeit.
+const REPLY_GRACE: Duration = Duration::from_secs(1);
+
+// How long each player has to make its moves
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum TimeControl {
+    // A clock starting at `base`, gaining `increment` per move, and
+    // gaining `base` again every `moves` moves if that is set
+    Clock { base: Duration, increment: Duration, moves: Option<u32> },
+    // A fixed time for every move
+    MoveTime(Duration),
+    // A fixed search depth, with no clock
+    Depth(usize),
+}
+
+impl TimeControl {
+    // Read a time control written as in tournament managers: "40/60+0.5"
+    // for 60 seconds per 40 moves plus 0.5 seconds a move, "60+0.5" or
+    // "60" for sudden death, "st=0.1" for 0.1 seconds a move, and
+    // "depth=4" for fixed depth
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        if let Some(depth) = text.strip_prefix("depth=") {
+            return depth.parse::<usize>().ok().filter(|depth| *depth > 0).map(TimeControl::Depth);
+        }
+        if let Some(seconds) = text.strip_prefix("st=") {
+            return parse_seconds(seconds).map(TimeControl::MoveTime);
+        }
+
+        let (moves, rest) = match text.find('/') {
+            Some(index) => (Some(text[..index].parse::<u32>().ok().filter(|moves| *moves > 0)?), &text[index + 1..]),
+            None => (None, text),
+        };
+        let (base, increment) = match rest.find('+') {
+            Some(index) => (parse_seconds(&rest[..index])?, parse_seconds(&rest[index + 1..])?),
+            None => (parse_seconds(rest)?, Duration::from_secs(0)),
+        };
+        Some(TimeControl::Clock { base, increment, moves })
+    }
+}
+
+fn parse_seconds(text: &str) -> Option<Duration> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+// What a player is told when asked for a move
+pub struct MoveRequest<'a> {
+    // The position the game started from, and the moves since
+    pub start: &'a GameState,
+    pub moves: &'a [ChessMove],
+    // The position to move in
+    pub state: &'a GameState,
+    pub time_control: TimeControl,
+    // Time left on each clock. Zero unless playing with a clock.
+    pub white_time: Duration,
+    pub black_time: Duration,
+    // Moves left until the clock is topped up, if it ever is
+    pub moves_to_go: Option<u32>,
+}
+
+impl<'a> MoveRequest<'a> {
+    // Search limits keeping to the time control
+    pub fn limits(&self) -> SearchLimits {
+        match self.time_control {
+            TimeControl::Clock { increment, .. } => {
+                let limits = SearchLimits::new()
+                    .clock(self.white_time, self.black_time, increment, increment);
+                match self.moves_to_go {
+                    Some(moves) => limits.moves_to_go(moves),
+                    None => limits,
+                }
+            },
+            TimeControl::MoveTime(time) => SearchLimits::new().move_time(time),
+            TimeControl::Depth(depth) => SearchLimits::new().depth(depth),
+        }
+    }
+
+    // The most time the side to move may take, if it is limited
+    pub fn time_left(&self) -> Option<Duration> {
+        match self.time_control {
+            TimeControl::Clock { .. } => Some(match self.state.to_move {
+                White => self.white_time,
+                Black => self.black_time,
+            }),
+            TimeControl::MoveTime(time) => Some(time),
+            TimeControl::Depth(_) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PlayerError {
+    Io(std::io::Error),
+    // The engine closed its output or exited
+    Disconnected,
+    TimedOut,
+    // The engine sent something that isn't a legal move
+    IllegalMove(String),
+    // There was no move to play
+    NoMove,
+}
+
+// One side of a match
+pub trait Player {
+    fn name(&self) -> String;
+
+    // Called before every game
+    fn new_game(&mut self) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    fn select_move(&mut self, request: &MoveRequest) -> Result<ChessMove, PlayerError>;
+}
+
+// A player searching in-process with the crate's own engine
+pub struct SearchPlayer {
+    name: String,
+    evaluator: Arc<dyn Evaluator>,
+    table: Arc<TranspositionTable>,
+}
+
+impl SearchPlayer {
+    pub fn new(name: &str) -> SearchPlayer {
+        SearchPlayer {
+            name: name.to_string(),
+            evaluator: Arc::new(ClassicalEvaluator::default()),
+            table: Arc::new(TranspositionTable::new(DEFAULT_TABLE_MEGABYTES)),
+        }
+    }
+
+    pub fn with_evaluator(mut self, evaluator: Arc<dyn Evaluator>) -> SearchPlayer {
+        self.evaluator = evaluator;
+        self
+    }
+}
+
+impl Player for SearchPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn new_game(&mut self) -> Result<(), PlayerError> {
+        self.table.clear();
+        Ok(())
+    }
+
+    fn select_move(&mut self, request: &MoveRequest) -> Result<ChessMove, PlayerError> {
+        Search::new(request.limits())
+            .with_evaluator(Arc::clone(&self.evaluator))
+            .with_transposition_table(Arc::clone(&self.table))
+            .run(request.state)
+            .best_move
+            .ok_or(PlayerError::NoMove)
+    }
+}
+
+// A UCI engine running as a child process. It is told to quit when the
+// player is dropped.
+pub struct UciPlayer {
+    name: String,
+    child: Child,
+    input: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciPlayer {
+    // Start `program` with `args` and wait for it to finish the handshake
+    pub fn start(program: &str, args: &[&str]) -> Result<UciPlayer, PlayerError> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(PlayerError::Io)?;
+        let input = child.stdin.take().unwrap();
+        let output = child.stdout.take().unwrap();
+
+        // Read on another thread, so that waiting for a reply can time out
+        let (sender, lines) = channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(output).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut player = UciPlayer { name: program.to_string(), child, input, lines };
+        player.send("uci")?;
+        loop {
+            let line = player.receive(Some(HANDSHAKE_TIMEOUT))?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                player.name = name.trim().to_string();
+            }
+            if line.trim() == "uciok" {
+                break;
+            }
+        }
+        Ok(player)
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), PlayerError> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    fn send(&mut self, text: &str) -> Result<(), PlayerError> {
+        writeln!(self.input, "{}", text)
+            .and_then(|_| self.input.flush())
+            .map_err(|_| PlayerError::Disconnected)
+    }
+
+    // The next line of output, waiting at most `timeout` if that is set
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<String, PlayerError> {
+        match timeout {
+            None => self.lines.recv().map_err(|_| PlayerError::Disconnected),
+            Some(timeout) => self.lines.recv_timeout(timeout).map_err(|error| match error {
+                RecvTimeoutError::Timeout => PlayerError::TimedOut,
+                RecvTimeoutError::Disconnected => PlayerError::Disconnected,
+            }),
+        }
+    }
+}
+
+impl Player for UciPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn new_game(&mut self) -> Result<(), PlayerError> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        // Anything left over from the last game is skipped
+        while self.receive(Some(HANDSHAKE_TIMEOUT))?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    fn select_move(&mut self, request: &MoveRequest) -> Result<ChessMove, PlayerError> {
+        let mut position = if request.start.hash() == GameState::new().hash() {
+            "position startpos".to_string()
+        } else {
+            format!("position fen {}", uci_fen(request.start))
+        };
+        if !request.moves.is_empty() {
+            position.push_str(" moves");
+            let mut state = *request.start;
+            for action in request.moves.iter() {
+                position.push(' ');
+                position.push_str(&to_uci(&state, action));
+                state = action.apply(&state);
+            }
+        }
+        self.send(&position)?;
+
+        let go = match request.time_control {
+            TimeControl::Clock { increment, .. } => {
+                let mut go = format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    request.white_time.as_millis(),
+                    request.black_time.as_millis(),
+                    increment.as_millis(),
+                    increment.as_millis(),
+                );
+                if let Some(moves) = request.moves_to_go {
+                    go.push_str(&format!(" movestogo {}", moves));
+                }
+                go
+            },
+            TimeControl::MoveTime(time) => format!("go movetime {}", time.as_millis()),
+            TimeControl::Depth(depth) => format!("go depth {}", depth),
+        };
+        self.send(&go)?;
+
+        let deadline = request.time_left().map(|time| Instant::now() + time + REPLY_GRACE);
+        loop {
+            let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let line = match self.receive(timeout) {
+                Ok(line) => line,
+                Err(PlayerError::TimedOut) => {
+                    let _ = self.send("stop");
+                    return Err(PlayerError::TimedOut);
+                },
+                Err(error) => return Err(error),
+            };
+            let mut tokens = line.split_whitespace();
+            if tokens.next() == Some("bestmove") {
+                let text = tokens.next().unwrap_or("");
+                return parse_uci_move(request.state, text)
+                    .map_err(|_| PlayerError::IllegalMove(text.to_string()));
+            }
+        }
+    }
+}
+
+impl Drop for UciPlayer {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let started = Instant::now();
+        while started.elapsed() < HANDSHAKE_TIMEOUT {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// fen_notation leaves the move number at 0, which some engines reject
+fn uci_fen(state: &GameState) -> String {
+    let fen = fen_notation(state);
+    let fields: Vec<&str> = fen.split_whitespace().take(4).collect();
+    format!("{} 0 1", fields.join(" "))
+}
+
+// A position to start games from, and any moves already played from it
+#[derive(Clone)]
+pub struct Opening {
+    pub start: GameState,
+    pub moves: Vec<ChessMove>,
+}
+
+impl Opening {
+    pub fn new(start: GameState) -> Opening {
+        Opening { start, moves: vec![] }
+    }
+
+    // The position after the opening's moves
+    pub fn state(&self) -> GameState {
+        self.moves.iter().fold(self.start, |state, action| action.apply(&state))
+    }
+}
+
+#[derive(Debug)]
+pub enum OpeningError {
+    Io(std::io::Error),
+    // The line number of an EPD position, or the number of a PGN game,
+    // counting from 1, and what was wrong with it
+    InvalidFen(usize, FenError),
+    IllegalMove(usize, String),
+}
+
+// Read one position per line in Extended Position Description. Anything
+// after the four position fields, like operations or clocks, is ignored,
+// as are blank lines and lines starting with '#'.
+pub fn read_epd_openings(text: &str) -> Result<Vec<Opening>, OpeningError> {
+    let mut openings = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+        let state = GameState::from_fen(&fields.join(" "))
+            .map_err(|error| OpeningError::InvalidFen(index + 1, error))?;
+        openings.push(Opening::new(state));
+    }
+    Ok(openings)
+}
+
+// Read the games of a PGN file as openings, starting from the FEN tag if
+// there is one. Comments, variations and annotations are skipped.
+pub fn read_pgn_openings(text: &str) -> Result<Vec<Opening>, OpeningError> {
+    let mut openings = vec![];
+    let mut fen: Option<String> = None;
+    let mut movetext = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            // A tag after movetext starts the next game
+            if !movetext.trim().is_empty() {
+                openings.push(pgn_opening(openings.len() + 1, fen.take(), &movetext)?);
+                movetext.clear();
+            }
+            if let Some(value) = line.strip_prefix("[FEN ") {
+                fen = Some(value.trim_end_matches(']').trim().trim_matches('"').to_string());
+            }
+        } else if !line.starts_with('%') {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    if !movetext.trim().is_empty() || fen.is_some() {
+        openings.push(pgn_opening(openings.len() + 1, fen, &movetext)?);
+    }
+    Ok(openings)
+}
+
+// Read openings from an EPD file, or from a PGN file if the path ends
+// in ".pgn"
+pub fn load_openings<P: AsRef<Path>>(path: P) -> Result<Vec<Opening>, OpeningError> {
+    let is_pgn = path.as_ref()
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pgn"));
+    let text = std::fs::read_to_string(path).map_err(OpeningError::Io)?;
+    if is_pgn {
+        read_pgn_openings(&text)
+    } else {
+        read_epd_openings(&text)
+    }
+}
+
+fn pgn_opening(number: usize, fen: Option<String>, movetext: &str) -> Result<Opening, OpeningError> {
+    let start = match fen {
+        Some(fen) => GameState::from_fen(&fen).map_err(|error| OpeningError::InvalidFen(number, error))?,
+        None => GameState::new(),
+    };
+    let mut opening = Opening::new(start);
+    let mut state = start;
+    for token in movetext_tokens(movetext) {
+        let action = parse_san(&state, &token)
+            .ok_or_else(|| OpeningError::IllegalMove(number, token.clone()))?;
+        state = action.apply(&state);
+        opening.moves.push(action);
+    }
+    Ok(opening)
+}
+
+// The moves of PGN movetext, without move numbers, comments, variations,
+// annotations or the result
+fn movetext_tokens(movetext: &str) -> Vec<String> {
+    let mut plain = String::new();
+    let mut comment = false;
+    let mut rest_of_line = false;
+    let mut variations = 0;
+    for c in movetext.chars() {
+        match c {
+            '\n' if rest_of_line => rest_of_line = false,
+            _ if rest_of_line => (),
+            '}' if comment => comment = false,
+            _ if comment => (),
+            '{' => comment = true,
+            ';' => rest_of_line = true,
+            '(' => variations += 1,
+            ')' => variations -= 1,
+            _ if variations > 0 => (),
+            // Move numbers may be written against the move, as in "1.e4"
+            '.' => plain.push(' '),
+            _ => plain.push(c),
+        }
+    }
+
+    plain.split_whitespace()
+        .filter(|token| !token.starts_with('$'))
+        .filter(|token| !token.chars().all(|c| c.is_ascii_digit()))
+        .filter(|token| !["1-0", "0-1", "1/2-1/2", "*"].contains(token))
+        .map(|token| token.to_string())
+        .collect()
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn to_pgn(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+// Why a game ended
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    // Adjudicated a draw after the match's move limit
+    MoveLimit,
+    TimeForfeit,
+    IllegalMove,
+    // A player crashed or couldn't answer
+    Disconnected,
+}
+
+impl Termination {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "threefold repetition",
+            Termination::FiftyMoves => "fifty move rule",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::MoveLimit => "move limit",
+            Termination::TimeForfeit => "time forfeit",
+            Termination::IllegalMove => "illegal move",
+            Termination::Disconnected => "disconnected",
+        }
+    }
+
+    // The value of the PGN Termination tag
+    fn pgn_tag(&self) -> &'static str {
+        match self {
+            Termination::MoveLimit => "adjudication",
+            Termination::TimeForfeit => "time forfeit",
+            Termination::IllegalMove => "rules infraction",
+            Termination::Disconnected => "abandoned",
+            _ => "normal",
+        }
+    }
+}
+
+// A finished game
+#[derive(Clone)]
+pub struct Game {
+    pub white: String,
+    pub black: String,
+    pub round: usize,
+    pub start: GameState,
+    pub moves: Vec<ChessMove>,
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl Game {
+    pub fn final_state(&self) -> GameState {
+        self.moves.iter().fold(self.start, |state, action| action.apply(&state))
+    }
+
+    // The game in Portable Game Notation
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        let tags = [
+            ("Event", "Match".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", "????.??.??".to_string()),
+            ("Round", self.round.to_string()),
+            ("White", self.white.clone()),
+            ("Black", self.black.clone()),
+            ("Result", self.result.to_pgn().to_string()),
+        ];
+        for (name, value) in tags.iter() {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "'")));
+        }
+        if self.start.hash() != GameState::new().hash() {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!("[FEN \"{}\"]\n", uci_fen(&self.start)));
+        }
+        pgn.push_str(&format!("[Termination \"{}\"]\n\n", self.termination.pgn_tag()));
+
+        let mut tokens = vec![];
+        let mut state = self.start;
+        if state.to_move == Black && !self.moves.is_empty() {
+            tokens.push("1...".to_string());
+        }
+        for (ply, action) in self.moves.iter().enumerate() {
+            if state.to_move == White {
+                tokens.push(format!("{}.", ply / 2 + 1 + (self.start.to_move == Black) as usize));
+            }
+            tokens.push(pgn_san(&state, action));
+            state = action.apply(&state);
+        }
+        tokens.push(format!("{{{}}}", self.termination.description()));
+        tokens.push(self.result.to_pgn().to_string());
+
+        // Keep lines under 80 characters
+        let mut line_length = 0;
+        for token in tokens.iter() {
+            if line_length > 0 && line_length + 1 + token.len() > 79 {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            pgn.push_str(token);
+            line_length += token.len();
+        }
+        pgn.push_str("\n\n");
+        pgn
+    }
+}
+
+// The crate's algebraic notation, with the '=' of promotions and the
+// check marks that PGN readers expect
+fn pgn_san(state: &GameState, action: &ChessMove) -> String {
+    let mut san = action.as_algebraic_notation(state);
+    if let ChessMove::Promotion(_) = action {
+        san.insert(san.len() - 1, '=');
+    }
+    let next_state = action.apply(state);
+    if is_checkmate(&next_state) {
+        san.push('#');
+    } else if color_is_checked(next_state.to_move, &next_state) {
+        san.push('+');
+    }
+    san
+}
+
+// The result of a position by the rules, given the keys of every earlier
+// position and the plies since the last capture or pawn move
+pub(crate) fn outcome(state: &GameState, keys: &[u64], halfmove_clock: usize) -> Option<(GameResult, Termination)> {
+    if is_checkmate(state) {
+        let result = match state.to_move {
+            White => GameResult::BlackWins,
+            Black => GameResult::WhiteWins,
+        };
+        return Some((result, Termination::Checkmate));
+    }
+    if is_stalemate(state) {
+        return Some((GameResult::Draw, Termination::Stalemate));
+    }
+    if halfmove_clock >= 100 {
+        return Some((GameResult::Draw, Termination::FiftyMoves));
+    }
+    let key = state.hash();
+    if keys.iter().filter(|earlier| **earlier == key).count() >= 2 {
+        return Some((GameResult::Draw, Termination::Repetition));
+    }
+    if insufficient_material(state) {
+        return Some((GameResult::Draw, Termination::InsufficientMaterial));
+    }
+    None
+}
+
+// Neither side can mate: bare kings, or a single minor piece between them
+pub(crate) fn insufficient_material(state: &GameState) -> bool {
+    let mut minor_pieces = 0;
+    for piece in state.squares.iter().flatten() {
+        match piece.name {
+            PieceName::King => (),
+            PieceName::Bishop | PieceName::Knight => minor_pieces += 1,
+            _ => return false,
+        }
+    }
+    minor_pieces <= 1
+}
+
+// Whether a move resets the fifty move rule
+pub(crate) fn is_irreversible(state: &GameState, action: &ChessMove) -> bool {
+    match action {
+        ChessMove::Move(action) => state.squares[action.from].map(|piece| piece.name) == Some(PieceName::Pawn),
+        ChessMove::Castle(_) => false,
+        _ => true,
+    }
+}
+
+// Called with each finished game of a match
+pub type GameCallback = Box<dyn FnMut(&Game)>;
+
+// Games between two players, alternating colours and openings. Each
+// opening is played twice, once with each player as white.
+pub struct Match {
+    games: usize,
+    time_control: TimeControl,
+    openings: Vec<Opening>,
+    max_plies: usize,
+    on_game: Option<GameCallback>,
+    sprt: Option<Sprt>,
+}
+
+// The games of a match, and its score from the first player's side
+pub struct MatchResult {
+    pub first: String,
+    pub second: String,
+    pub games: Vec<Game>,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Match {
+    pub fn new(games: usize, time_control: TimeControl) -> Match {
+        Match {
+            games,
+            time_control,
+            openings: vec![],
+            max_plies: DEFAULT_MAX_PLIES,
+            on_game: None,
+            sprt: None,
+        }
+    }
+
+    // Start games from these openings in turn, instead of the initial
+    // position
+    pub fn openings(mut self, openings: Vec<Opening>) -> Match {
+        self.openings = openings;
+        self
+    }
+
+    // Adjudicate games as draws after this many plies
+    pub fn max_plies(mut self, plies: usize) -> Match {
+        self.max_plies = plies;
+        self
+    }
+
+    // Called as each game finishes
+    pub fn on_game<F: FnMut(&Game) + 'static>(mut self, callback: F) -> Match {
+        self.on_game = Some(Box::new(callback));
+        self
+    }
+
+    // Stop early once the test decides, checking after each pair of games
+    pub fn sprt(mut self, sprt: Sprt) -> Match {
+        self.sprt = Some(sprt);
+        self
+    }
+
+    pub fn run(&mut self, first: &mut dyn Player, second: &mut dyn Player) -> MatchResult {
+        let mut result = MatchResult {
+            first: first.name(),
+            second: second.name(),
+            games: vec![],
+            wins: 0,
+            draws: 0,
+            losses: 0,
+        };
+
+        for round in 0..self.games {
+            let opening = match self.openings.len() {
+                0 => Opening::new(GameState::new()),
+                n => self.openings[(round / 2) % n].clone(),
+            };
+            let first_is_white = round.is_multiple_of(2);
+            let game = if first_is_white {
+                self.play(round + 1, &opening, first, second)
+            } else {
+                self.play(round + 1, &opening, second, first)
+            };
+
+            match first_half_points(round, &game) {
+                2 => result.wins += 1,
+                1 => result.draws += 1,
+                _ => result.losses += 1,
+            }
+            if let Some(callback) = self.on_game.as_mut() {
+                callback(&game);
+            }
+            result.games.push(game);
+
+            if let (Some(sprt), 1) = (self.sprt, round % 2) {
+                let llr = sprt.llr_pentanomial(&result.pentanomial());
+                if sprt.decision(llr) != SprtDecision::Continue {
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+
+    fn play(&self, round: usize, opening: &Opening, white: &mut dyn Player, black: &mut dyn Player) -> Game {
+        let mut game = Game {
+            white: white.name(),
+            black: black.name(),
+            round,
+            start: opening.start,
+            moves: vec![],
+            result: GameResult::Draw,
+            termination: Termination::MoveLimit,
+        };
+        let forfeit = |state: &GameState| match state.to_move {
+            White => GameResult::BlackWins,
+            Black => GameResult::WhiteWins,
+        };
+
+        let mut state = opening.start;
+        let mut keys = vec![];
+        let mut halfmove_clock = 0;
+        for action in opening.moves.iter() {
+            halfmove_clock = if is_irreversible(&state, action) { 0 } else { halfmove_clock + 1 };
+            keys.push(state.hash());
+            state = action.apply(&state);
+            game.moves.push(*action);
+        }
+
+        if white.new_game().is_err() {
+            game.result = GameResult::BlackWins;
+            game.termination = Termination::Disconnected;
+            return game;
+        }
+        if black.new_game().is_err() {
+            game.result = GameResult::WhiteWins;
+            game.termination = Termination::Disconnected;
+            return game;
+        }
+
+        let (base, increment, session) = match self.time_control {
+            TimeControl::Clock { base, increment, moves } => (base, increment, moves),
+            _ => (Duration::from_secs(0), Duration::from_secs(0), None),
+        };
+        let mut clocks = [base, base];
+        let mut moves_made = [0, 0];
+
+        while game.moves.len() < self.max_plies + opening.moves.len() {
+            if let Some((result, termination)) = outcome(&state, &keys, halfmove_clock) {
+                game.result = result;
+                game.termination = termination;
+                return game;
+            }
+
+            let side = match state.to_move {
+                White => 0,
+                Black => 1,
+            };
+            let moves_to_go = session.map(|moves| moves - moves_made[side] % moves);
+            let request = MoveRequest {
+                start: &game.start,
+                moves: &game.moves,
+                state: &state,
+                time_control: self.time_control,
+                white_time: clocks[0],
+                black_time: clocks[1],
+                moves_to_go,
+            };
+            let started = Instant::now();
+            let reply = match state.to_move {
+                White => white.select_move(&request),
+                Black => black.select_move(&request),
+            };
+            let elapsed = started.elapsed();
+
+            let action = match reply {
+                Ok(action) if action.is_legal(&state) => action,
+                Ok(_) | Err(PlayerError::IllegalMove(_)) => {
+                    game.result = forfeit(&state);
+                    game.termination = Termination::IllegalMove;
+                    return game;
+                },
+                Err(PlayerError::TimedOut) => {
+                    game.result = forfeit(&state);
+                    game.termination = Termination::TimeForfeit;
+                    return game;
+                },
+                Err(_) => {
+                    game.result = forfeit(&state);
+                    game.termination = Termination::Disconnected;
+                    return game;
+                },
+            };
+
+            if let TimeControl::Clock { .. } = self.time_control {
+                if elapsed > clocks[side] {
+                    game.result = forfeit(&state);
+                    game.termination = Termination::TimeForfeit;
+                    return game;
+                }
+                clocks[side] = clocks[side] - elapsed + increment;
+                moves_made[side] += 1;
+                if session.is_some_and(|moves| moves_made[side] % moves == 0) {
+                    clocks[side] += base;
+                }
+            }
+
+            halfmove_clock = if is_irreversible(&state, &action) { 0 } else { halfmove_clock + 1 };
+            keys.push(state.hash());
+            state = action.apply(&state);
+            game.moves.push(action);
+        }
+
+        match outcome(&state, &keys, halfmove_clock) {
+            Some((result, termination)) => {
+                game.result = result;
+                game.termination = termination;
+            },
+            None => {
+                game.result = GameResult::Draw;
+                game.termination = Termination::MoveLimit;
+            },
+        }
+        game
+    }
+}
+
+// The first player plays white in even rounds, counting from 0
+fn first_half_points(round: usize, game: &Game) -> usize {
+    let first_is_white = round.is_multiple_of(2);
+    match (game.result, first_is_white) {
+        (GameResult::Draw, _) => 1,
+        (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => 2,
+        _ => 0,
+    }
+}
+
+impl MatchResult {
+    // The first player's share of the points, from 0 to 1
+    pub fn score(&self) -> f64 {
+        let games = self.wins + self.draws + self.losses;
+        if games == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + 0.5 * self.draws as f64) / games as f64
+    }
+
+    pub fn elo(&self) -> EloEstimate {
+        elo_from_wdl(self.wins, self.draws, self.losses)
+    }
+
+    // How many pairs of games with the same opening the first player
+    // scored 0 to 4 half points in
+    pub fn pentanomial(&self) -> [usize; 5] {
+        let mut pairs = [0; 5];
+        for (pair, games) in self.games.chunks_exact(2).enumerate() {
+            let half_points = first_half_points(2 * pair, &games[0]) + first_half_points(2 * pair + 1, &games[1]);
+            pairs[half_points] += 1;
+        }
+        pairs
+    }
+
+    pub fn summary(&self) -> String {
+        let elo = self.elo();
+        format!(
+            "{} vs {}: {} wins, {} draws, {} losses ({:.1}%) in {} games, Elo {:+.1} +/- {:.1}, LOS {:.1}%",
+            self.first,
+            self.second,
+            self.wins,
+            self.draws,
+            self.losses,
+            100.0 * self.score(),
+            self.games.len(),
+            elo.elo,
+            elo.error,
+            100.0 * elo.los,
+        )
+    }
+
+    // Every game in Portable Game Notation
+    pub fn to_pgn(&self) -> String {
+        self.games.iter().map(|game| game.to_pgn()).collect()
+    }
+
+    pub fn save_pgn<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_pgn())
+    }
+}
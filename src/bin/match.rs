@@ -0,0 +1,94 @@
+// Play a match between two engines and write the games to a PGN file.
+//
+// usage: match FIRST SECOND GAMES TIME_CONTROL [OPENINGS] [OUTPUT]
+//
+// FIRST and SECOND are commands starting UCI engines, or "builtin" for
+// this crate's own search. TIME_CONTROL is written like "40/60+0.5",
+// "10+0.1", "st=0.5" or "depth=4". OPENINGS is an EPD or PGN file, and
+// each opening is played once with each engine as white.
+
+use chess_engine::{
+    load_openings,
+    Match,
+    Player,
+    SearchPlayer,
+    TimeControl,
+    UciPlayer,
+};
+
+use std::process::exit;
+
+const USAGE: &str = "usage: match FIRST SECOND GAMES TIME_CONTROL [OPENINGS] [OUTPUT]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 4 || args.len() > 6 {
+        eprintln!("{}", USAGE);
+        exit(2);
+    }
+
+    let mut first = start_player(&args[0]);
+    let mut second = start_player(&args[1]);
+    let games = match args[2].parse::<usize>() {
+        Ok(games) => games,
+        Err(_) => {
+            eprintln!("GAMES must be a number");
+            exit(2);
+        },
+    };
+    let time_control = match TimeControl::parse(&args[3]) {
+        Some(time_control) => time_control,
+        None => {
+            eprintln!("couldn't read time control {}", args[3]);
+            exit(2);
+        },
+    };
+
+    let mut runner = Match::new(games, time_control).on_game(|game| {
+        eprintln!(
+            "game {}: {} vs {}: {} ({})",
+            game.round,
+            game.white,
+            game.black,
+            game.result.to_pgn(),
+            game.termination.description(),
+        );
+    });
+    if let Some(path) = args.get(4) {
+        match load_openings(path) {
+            Ok(openings) => runner = runner.openings(openings),
+            Err(error) => {
+                eprintln!("couldn't read openings: {:?}", error);
+                exit(1);
+            },
+        }
+    }
+
+    let result = runner.run(first.as_mut(), second.as_mut());
+    println!("{}", result.summary());
+
+    if let Some(path) = args.get(5) {
+        if let Err(error) = result.save_pgn(path) {
+            eprintln!("couldn't write games: {}", error);
+            exit(1);
+        }
+    }
+}
+
+fn start_player(command: &str) -> Box<dyn Player> {
+    if command == "builtin" {
+        return Box::new(SearchPlayer::new("builtin"));
+    }
+    let words: Vec<&str> = command.split_whitespace().collect();
+    if words.is_empty() {
+        eprintln!("{}", USAGE);
+        exit(2);
+    }
+    match UciPlayer::start(words[0], &words[1..]) {
+        Ok(player) => Box::new(player),
+        Err(error) => {
+            eprintln!("couldn't start {}: {:?}", command, error);
+            exit(1);
+        },
+    }
+}
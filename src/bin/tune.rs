@@ -0,0 +1,71 @@
+// Fit the classical evaluation weights to a file of labelled positions.
+//
+// usage: tune POSITIONS OUTPUT [PASSES] [INITIAL_WEIGHTS]
+//
+// POSITIONS holds one FEN and game result per line, and the tuned weights
+// are written to OUTPUT after every pass, so tuning can be interrupted.
+
+use chess_engine::{
+    load_labelled_positions,
+    EvaluationWeights,
+    Tuner,
+};
+
+use std::process::exit;
+
+const DEFAULT_PASSES: usize = 100;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 || args.len() > 4 {
+        eprintln!("usage: tune POSITIONS OUTPUT [PASSES] [INITIAL_WEIGHTS]");
+        exit(2);
+    }
+
+    let positions = match load_labelled_positions(&args[0]) {
+        Ok(positions) => positions,
+        Err(error) => {
+            eprintln!("couldn't read positions: {:?}", error);
+            exit(1);
+        },
+    };
+    let passes = match args.get(2).map(|passes| passes.parse::<usize>()) {
+        None => DEFAULT_PASSES,
+        Some(Ok(passes)) => passes,
+        Some(Err(_)) => {
+            eprintln!("PASSES must be a number");
+            exit(2);
+        },
+    };
+    let mut weights = match args.get(3) {
+        None => EvaluationWeights::default(),
+        Some(path) => match EvaluationWeights::load(path) {
+            Ok(weights) => weights,
+            Err(error) => {
+                eprintln!("couldn't read initial weights: {:?}", error);
+                exit(1);
+            },
+        },
+    };
+
+    let mut tuner = Tuner::new(positions);
+    let scaling = tuner.fit_scaling(&weights);
+    eprintln!(
+        "{} positions, scaling {:.3}, error {:.6}",
+        tuner.positions().len(),
+        scaling,
+        tuner.error(&weights),
+    );
+
+    for pass in 1..=passes {
+        let improved = tuner.tune_pass(&mut weights);
+        eprintln!("pass {}: error {:.6}", pass, tuner.error(&weights));
+        if let Err(error) = weights.save(&args[1]) {
+            eprintln!("couldn't write weights: {}", error);
+            exit(1);
+        }
+        if !improved {
+            break;
+        }
+    }
+}
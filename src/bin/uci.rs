@@ -0,0 +1,10 @@
+// A chess engine speaking the Universal Chess Interface on stdin and
+// stdout, for use with chess GUIs and tournament managers.
+
+use chess_engine::UciEngine;
+
+fn main() {
+    let stdin = std::io::stdin();
+    let mut engine = UciEngine::new(std::io::stdout());
+    engine.run(stdin.lock());
+}
@@ -0,0 +1,10 @@
+// A chess engine speaking the Chess Engine Communication Protocol on
+// stdin and stdout, for use with XBoard, WinBoard and similar GUIs.
+
+use chess_engine::CecpEngine;
+
+fn main() {
+    let stdin = std::io::stdin();
+    let mut engine = CecpEngine::new(std::io::stdout());
+    engine.run(stdin.lock());
+}
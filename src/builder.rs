@@ -0,0 +1,203 @@
+// The single responsibility of this module is to assemble custom
+// GameStates, refusing to produce positions that fail validation.
+
+use crate::gamestate::{
+    GameState,
+    Placement,
+};
+
+use crate::pieces::{
+    Piece,
+    Color,
+    Color::{White, Black},
+};
+
+use crate::actions::CastleDirection::{self, Kingside, Queenside};
+
+use crate::validation::PositionError;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum BuilderError {
+    SquareOutOfRange(usize),
+    SquareOccupied(usize),
+    SquareEmpty(usize),
+    InvalidPosition(Vec<PositionError>),
+}
+
+// Edits are applied in order. The first edit that can't be carried out
+// is remembered, every edit after it is ignored, and build() reports it.
+pub struct GameStateBuilder {
+    state: GameState,
+    error: Option<BuilderError>,
+}
+
+impl GameStateBuilder {
+    // Start from an empty board with white to move and no castling rights
+    pub fn new() -> GameStateBuilder {
+        GameStateBuilder::from_state(GameState::with_placements(vec![]))
+    }
+
+    pub fn from_state(state: GameState) -> GameStateBuilder {
+        GameStateBuilder { state, error: None }
+    }
+
+    // Start from the standard opening position
+    pub fn starting_position() -> GameStateBuilder {
+        GameStateBuilder::from_state(GameState::new())
+    }
+
+    // The position as edited so far, whether or not it is valid
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn put(mut self, piece: Piece, square: usize) -> GameStateBuilder {
+        if self.error.is_some() {
+            return self;
+        }
+        if square > 63 {
+            self.error = Some(BuilderError::SquareOutOfRange(square));
+        } else if self.state.squares[square].is_some() {
+            self.error = Some(BuilderError::SquareOccupied(square));
+        } else {
+            self.state.squares[square] = Some(piece);
+        }
+        self
+    }
+
+    pub fn placement(self, placement: Placement) -> GameStateBuilder {
+        let piece = Piece::new(placement.color, placement.piece);
+        self.put(piece, placement.square)
+    }
+
+    pub fn remove(mut self, square: usize) -> GameStateBuilder {
+        if self.error.is_some() {
+            return self;
+        }
+        if square > 63 {
+            self.error = Some(BuilderError::SquareOutOfRange(square));
+        } else if self.state.squares[square].is_none() {
+            self.error = Some(BuilderError::SquareEmpty(square));
+        } else {
+            self.state.squares[square] = None;
+        }
+        self
+    }
+
+    pub fn move_piece(mut self, from: usize, to: usize) -> GameStateBuilder {
+        if self.error.is_some() {
+            return self;
+        }
+        if from > 63 {
+            self.error = Some(BuilderError::SquareOutOfRange(from));
+            return self;
+        }
+        match self.state.squares[from] {
+            None => {
+                self.error = Some(BuilderError::SquareEmpty(from));
+                self
+            },
+            Some(piece) => self.remove(from).put(piece, to),
+        }
+    }
+
+    // Remove every piece, leaving the other state untouched
+    pub fn clear(mut self) -> GameStateBuilder {
+        if self.error.is_none() {
+            self.state.squares = [None; 64];
+        }
+        self
+    }
+
+    pub fn to_move(mut self, color: Color) -> GameStateBuilder {
+        self.state.to_move = color;
+        self
+    }
+
+    pub fn castling_right(
+        mut self,
+        color: Color,
+        direction: CastleDirection,
+        allowed: bool,
+    ) -> GameStateBuilder {
+        match (color, direction) {
+            (White, Kingside) => self.state.white_can_castle_kingside = allowed,
+            (White, Queenside) => self.state.white_can_castle_queenside = allowed,
+            (Black, Kingside) => self.state.black_can_castle_kingside = allowed,
+            (Black, Queenside) => self.state.black_can_castle_queenside = allowed,
+        }
+        self
+    }
+
+    pub fn en_passant_square(mut self, square: Option<usize>) -> GameStateBuilder {
+        if self.error.is_some() {
+            return self;
+        }
+        match square {
+            Some(square) if square > 63 => {
+                self.error = Some(BuilderError::SquareOutOfRange(square));
+            },
+            _ => self.state.en_passant_square = square,
+        }
+        self
+    }
+
+    // Reflect the board left to right, so the a-file becomes the h-file.
+    // Kings and rooks leave their castling squares, so castling rights
+    // are dropped.
+    pub fn mirror(mut self) -> GameStateBuilder {
+        let squares = self.state.squares;
+        for (square, piece) in squares.iter().enumerate() {
+            self.state.squares[square ^ 7] = *piece;
+        }
+        self.state.en_passant_square = self.state.en_passant_square.map(|s| s ^ 7);
+        self.state.white_can_castle_kingside = false;
+        self.state.white_can_castle_queenside = false;
+        self.state.black_can_castle_kingside = false;
+        self.state.black_can_castle_queenside = false;
+        self
+    }
+
+    // Reflect the board top to bottom and swap the colors of every piece,
+    // the side to move, and the castling rights. The result is the same
+    // position seen from the other player's side.
+    pub fn flip(mut self) -> GameStateBuilder {
+        let squares = self.state.squares;
+        for (square, piece) in squares.iter().enumerate() {
+            self.state.squares[square ^ 56] = piece.map(|p| {
+                let color = if p.color == White { Black } else { White };
+                Piece::new(color, p.name)
+            });
+        }
+        let state = &mut self.state;
+        state.to_move = if state.to_move == White { Black } else { White };
+        state.en_passant_square = state.en_passant_square.map(|s| s ^ 56);
+        std::mem::swap(
+            &mut state.white_can_castle_kingside,
+            &mut state.black_can_castle_kingside,
+        );
+        std::mem::swap(
+            &mut state.white_can_castle_queenside,
+            &mut state.black_can_castle_queenside,
+        );
+        self
+    }
+
+    pub fn build(self) -> Result<GameState, BuilderError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        match self.state.validate() {
+            Ok(()) => Ok(self.state),
+            Err(errors) => Err(BuilderError::InvalidPosition(errors)),
+        }
+    }
+}
+
+impl Default for GameStateBuilder {
+    fn default() -> GameStateBuilder {
+        GameStateBuilder::new()
+    }
+}
@@ -0,0 +1,393 @@
+// The single responsibility of this module is to let older chess GUIs
+// drive the search through the Chess Engine Communication Protocol, also
+// known as the XBoard or WinBoard protocol.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    Color,
+    Color::{White, Black},
+};
+
+use crate::actions::Action;
+
+use crate::utilities::{
+    is_checkmate,
+    is_stalemate,
+};
+
+use crate::search::{
+    Search,
+    SearchLimits,
+};
+
+use crate::transposition::{
+    TranspositionTable,
+    DEFAULT_TABLE_MEGABYTES,
+};
+
+use crate::notation::{
+    to_uci,
+    parse_uci_move,
+};
+
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// The game as the GUI sees it. The search thread adds its own moves, so
+// that a move is never announced without being made.
+struct Game {
+    // Every position so far, starting with the first
+    history: Vec<GameState>,
+    // The side the engine plays, or None in force mode
+    engine_color: Option<Color>,
+}
+
+impl Game {
+    fn new() -> Game {
+        Game { history: vec![GameState::new()], engine_color: Some(Black) }
+    }
+
+    fn state(&self) -> GameState {
+        *self.history.last().unwrap()
+    }
+}
+
+// Time controls set by `level`, `st` and `sd`
+#[derive(Default)]
+#[derive(Copy)]
+#[derive(Clone)]
+struct TimeControl {
+    moves_per_session: u32,
+    increment: Duration,
+    move_time: Option<Duration>,
+    depth: Option<usize>,
+    engine_time: Option<Duration>,
+    opponent_time: Option<Duration>,
+}
+
+struct Thinking {
+    stop: Arc<AtomicBool>,
+    // Raised when the search's move should be thrown away, not played
+    cancel: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+// A CECP engine writing to `output`. Searches run on a background thread,
+// so that commands like `?` and `force` are handled while it thinks.
+pub struct CecpEngine<W: Write + Send + 'static> {
+    output: Arc<Mutex<W>>,
+    game: Arc<Mutex<Game>>,
+    table: Arc<TranspositionTable>,
+    time_control: TimeControl,
+    post: bool,
+    thinking: Option<Thinking>,
+}
+
+impl<W: Write + Send + 'static> CecpEngine<W> {
+    pub fn new(output: W) -> CecpEngine<W> {
+        CecpEngine {
+            output: Arc::new(Mutex::new(output)),
+            game: Arc::new(Mutex::new(Game::new())),
+            table: Arc::new(TranspositionTable::new(DEFAULT_TABLE_MEGABYTES)),
+            time_control: TimeControl::default(),
+            post: false,
+            thinking: None,
+        }
+    }
+
+    // Handle commands until `quit` or the end of the input, then wait for
+    // the engine to finish thinking
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if !self.handle(&line) {
+                return;
+            }
+        }
+        self.wait_for_move();
+    }
+
+    // Handle one command. Returns false once the engine should exit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let argument = |index: usize| tokens.get(index).copied().unwrap_or("");
+
+        match tokens.first().copied().unwrap_or("") {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer" => (),
+            "protover" => self.send(&format!(
+                "feature myname=\"{} {}\" usermove=1 setboard=1 ping=1 playother=1 \
+                 colors=0 analyze=0 sigint=0 sigterm=0 done=1",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+            )),
+            "ping" => self.send(&format!("pong {}", argument(1))),
+            "new" => {
+                self.cancel_thinking();
+                *self.game.lock().unwrap() = Game::new();
+                self.time_control.depth = None;
+                self.table.clear();
+            },
+            "force" | "result" => {
+                self.cancel_thinking();
+                self.game.lock().unwrap().engine_color = None;
+            },
+            "go" => {
+                self.cancel_thinking();
+                let mut game = self.game.lock().unwrap();
+                game.engine_color = Some(game.state().to_move);
+                drop(game);
+                self.think();
+            },
+            "playother" => {
+                self.cancel_thinking();
+                let mut game = self.game.lock().unwrap();
+                game.engine_color = Some(opposite(game.state().to_move));
+            },
+            "usermove" => self.user_move(argument(1)),
+            "?" => self.wait_for_move_now(),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "setboard" => {
+                self.cancel_thinking();
+                match GameState::from_fen(&tokens[1..].join(" ")) {
+                    Ok(state) => self.game.lock().unwrap().history = vec![state],
+                    Err(_) => self.send("tellusererror Illegal position"),
+                }
+            },
+            "level" => self.set_level(&tokens[1..]),
+            "st" => {
+                let seconds = argument(1).parse::<f64>().unwrap_or(0.0);
+                self.time_control.move_time = Some(Duration::from_secs_f64(seconds.max(0.0)));
+            },
+            "sd" => self.time_control.depth = argument(1).parse::<usize>().ok(),
+            "time" => self.time_control.engine_time = parse_centiseconds(argument(1)),
+            "otim" => self.time_control.opponent_time = parse_centiseconds(argument(1)),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "quit" => {
+                self.cancel_thinking();
+                return false;
+            },
+            // Protocol version 1 GUIs send moves without `usermove`
+            text => {
+                if looks_like_move(text) {
+                    self.user_move(text);
+                } else if !text.is_empty() {
+                    self.send(&format!("Error (unknown command): {}", text));
+                }
+            },
+        }
+        true
+    }
+
+    fn send(&self, text: &str) {
+        send(&self.output, text);
+    }
+
+    fn user_move(&mut self, text: &str) {
+        self.wait_for_move();
+        let mut game = self.game.lock().unwrap();
+        let state = game.state();
+        let action = match parse_uci_move(&state, text) {
+            Ok(action) => action,
+            Err(_) => {
+                drop(game);
+                self.send(&format!("Illegal move: {}", text));
+                return;
+            },
+        };
+        let next_state = action.apply(&state);
+        game.history.push(next_state);
+        let engine_to_move = game.engine_color == Some(next_state.to_move);
+        drop(game);
+
+        if let Some(result) = game_result(&next_state) {
+            self.send(&result);
+        } else if engine_to_move {
+            self.think();
+        }
+    }
+
+    fn take_back(&mut self, moves: usize) {
+        self.cancel_thinking();
+        let mut game = self.game.lock().unwrap();
+        for _ in 0..moves {
+            if game.history.len() > 1 {
+                game.history.pop();
+            }
+        }
+    }
+
+    // level MPS BASE INC, where BASE is minutes or minutes:seconds. Both
+    // clocks start at BASE until `time` and `otim` say otherwise.
+    fn set_level(&mut self, tokens: &[&str]) {
+        let moves = tokens.first().and_then(|moves| moves.parse::<u32>().ok());
+        let base = tokens.get(1).and_then(|base| parse_base_time(base));
+        let increment = tokens.get(2).and_then(|seconds| seconds.parse::<f64>().ok());
+        if let (Some(moves), Some(base), Some(increment)) = (moves, base, increment) {
+            self.time_control.moves_per_session = moves;
+            self.time_control.increment = Duration::from_secs_f64(increment.max(0.0));
+            self.time_control.move_time = None;
+            self.time_control.engine_time = Some(base);
+            self.time_control.opponent_time = Some(base);
+        }
+    }
+
+    fn limits(&self, state: &GameState, moves_played: usize) -> SearchLimits {
+        let control = self.time_control;
+        let mut limits = SearchLimits::new();
+        if let Some(depth) = control.depth {
+            limits = limits.depth(depth);
+        }
+        if let Some(time) = control.move_time {
+            return limits.move_time(time);
+        }
+        if let Some(own) = control.engine_time {
+            let other = control.opponent_time.unwrap_or(own);
+            let (white, black) = match state.to_move {
+                White => (own, other),
+                Black => (other, own),
+            };
+            limits = limits.clock(white, black, control.increment, control.increment);
+            if control.moves_per_session > 0 {
+                let played = (moves_played / 2) as u32 % control.moves_per_session;
+                limits = limits.moves_to_go(control.moves_per_session - played);
+            }
+        }
+        limits
+    }
+
+    // Start searching for the engine's move in the background
+    fn think(&mut self) {
+        let (state, moves_played) = {
+            let game = self.game.lock().unwrap();
+            (game.state(), game.history.len() - 1)
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut search = Search::new(self.limits(&state, moves_played))
+            .with_stop_flag(Arc::clone(&stop))
+            .with_transposition_table(Arc::clone(&self.table));
+        if self.post {
+            let output = Arc::clone(&self.output);
+            search = search.on_iteration(move |info| {
+                send(&output, &format!(
+                    "{} {} {} {} {}",
+                    info.depth,
+                    info.score,
+                    info.elapsed.as_millis() / 10,
+                    info.nodes,
+                    info.principal_variation.join(" "),
+                ));
+            });
+        }
+
+        let output = Arc::clone(&self.output);
+        let game = Arc::clone(&self.game);
+        let thread_cancel = Arc::clone(&cancel);
+        let handle = std::thread::spawn(move || {
+            let result = search.run(&state);
+            let mut game = game.lock().unwrap();
+            if thread_cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let action = match result.best_move {
+                Some(action) => action,
+                None => return,
+            };
+            let next_state = action.apply(&state);
+            game.history.push(next_state);
+            send(&output, &format!("move {}", to_uci(&state, &action)));
+            if let Some(result) = game_result(&next_state) {
+                send(&output, &result);
+            }
+        });
+
+        self.thinking = Some(Thinking { stop, cancel, handle });
+    }
+
+    fn wait_for_move(&mut self) {
+        if let Some(thinking) = self.thinking.take() {
+            thinking.handle.join().unwrap();
+        }
+    }
+
+    // Play the best move found so far
+    fn wait_for_move_now(&mut self) {
+        if let Some(thinking) = self.thinking.as_ref() {
+            thinking.stop.store(true, Ordering::Relaxed);
+        }
+        self.wait_for_move();
+    }
+
+    // Stop thinking without playing a move
+    fn cancel_thinking(&mut self) {
+        if let Some(thinking) = self.thinking.as_ref() {
+            // Holding the game lock means the move can't be half made
+            let _game = self.game.lock().unwrap();
+            thinking.cancel.store(true, Ordering::Relaxed);
+            thinking.stop.store(true, Ordering::Relaxed);
+        }
+        self.wait_for_move();
+    }
+}
+
+fn send<W: Write>(output: &Mutex<W>, text: &str) {
+    let mut output = output.lock().unwrap();
+    // There is nobody to tell if the GUI has gone away
+    let _ = writeln!(output, "{}", text);
+    let _ = output.flush();
+}
+
+// Coordinate moves like "e2e4" and "e7e8q"
+fn looks_like_move(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    (bytes.len() == 4 || bytes.len() == 5)
+        && (b'a'..=b'h').contains(&bytes[0])
+        && (b'1'..=b'8').contains(&bytes[1])
+        && (b'a'..=b'h').contains(&bytes[2])
+        && (b'1'..=b'8').contains(&bytes[3])
+}
+
+fn parse_centiseconds(text: &str) -> Option<Duration> {
+    text.parse::<u64>().ok().map(|centiseconds| Duration::from_millis(10 * centiseconds))
+}
+
+// Minutes, or minutes:seconds
+fn parse_base_time(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = match text.split_once(':') {
+        Some((minutes, seconds)) => (minutes, seconds.parse::<u64>().ok()?),
+        None => (text, 0),
+    };
+    let minutes = minutes.parse::<u64>().ok()?;
+    Some(Duration::from_secs(60 * minutes + seconds))
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        White => Black,
+        Black => White,
+    }
+}
+
+// The result command for a finished game, if it is finished
+fn game_result(state: &GameState) -> Option<String> {
+    if is_checkmate(state) {
+        return Some(match state.to_move {
+            White => "0-1 {Black mates}".to_string(),
+            Black => "1-0 {White mates}".to_string(),
+        });
+    }
+    if is_stalemate(state) {
+        return Some("1/2-1/2 {Stalemate}".to_string());
+    }
+    None
+}
@@ -0,0 +1,309 @@
+// The single responsibility of this module is to present games of chess
+// as episodes for reinforcement learning, in the style of Gym: actions
+// are numbers, and each step returns an observation, a reward and whether
+// the episode is over.
+
+use crate::gamestate::{
+    GameState,
+    FenError,
+};
+
+use crate::pieces::{
+    PieceName,
+    Color,
+    Color::{White, Black},
+};
+
+use crate::actions::{
+    Action,
+    ChessMove,
+};
+
+use crate::utilities::{
+    legal_chess_moves,
+    relative_material_values,
+};
+
+use crate::notation::move_squares;
+
+use crate::arena::{
+    GameResult,
+    Termination,
+    DEFAULT_MAX_PLIES,
+    outcome,
+    is_irreversible,
+};
+
+use crate::agents::{
+    Agent,
+    Random,
+};
+
+// Actions are numbered by the piece promoted to, then the origin and
+// destination squares: (promotion * 64 + from) * 64 + to, where promotion
+// is 0 for a queen or no promotion, 1 for a knight, 2 for a bishop and 3
+// for a rook. Castling is the king's move, and en passant the pawn's.
+pub const ACTION_COUNT: usize = 4 * 64 * 64;
+
+pub fn action_index(state: &GameState, action: &ChessMove) -> usize {
+    let (from, to) = move_squares(state, action);
+    let promotion = match action {
+        ChessMove::Promotion(promotion) => match promotion.pawn_becomes {
+            PieceName::Knight => 1,
+            PieceName::Bishop => 2,
+            PieceName::Rook => 3,
+            _ => 0,
+        },
+        _ => 0,
+    };
+    (promotion * 64 + from) * 64 + to
+}
+
+// The legal move with this number, if there is one
+pub fn index_to_action(state: &GameState, index: usize) -> Option<ChessMove> {
+    legal_chess_moves(state)
+        .into_iter()
+        .find(|action| action_index(state, action) == index)
+}
+
+// Which action numbers are legal moves
+pub fn legal_action_mask(state: &GameState) -> Vec<bool> {
+    let mut mask = vec![false; ACTION_COUNT];
+    for action in legal_chess_moves(state) {
+        mask[action_index(state, &action)] = true;
+    }
+    mask
+}
+
+// How steps are rewarded. Rewards go to the player who took the step, at
+// the end of the game for its result and on every step for `material`
+// times the pawns' worth of material it gained relative to the opponent.
+// An episode cut short by the step limit earns nothing for its result.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct RewardScheme {
+    pub win: f64,
+    pub draw: f64,
+    pub loss: f64,
+    pub material: f64,
+}
+
+impl Default for RewardScheme {
+    fn default() -> RewardScheme {
+        RewardScheme::outcome()
+    }
+}
+
+impl RewardScheme {
+    // 1 for a win, 0 for a draw and -1 for a loss
+    pub fn outcome() -> RewardScheme {
+        RewardScheme { win: 1.0, draw: 0.0, loss: -1.0, material: 0.0 }
+    }
+
+    pub fn material_shaping(weight: f64) -> RewardScheme {
+        RewardScheme { material: weight, ..RewardScheme::outcome() }
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    // The action isn't a legal move in the current position
+    IllegalAction(usize),
+    // The episode is over, and the environment must be reset
+    EpisodeOver,
+}
+
+// What the agent sees after each step
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Observation {
+    pub state: GameState,
+    pub legal_mask: Vec<bool>,
+}
+
+// Details of a step that the reward doesn't show
+#[derive(Debug)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct StepInfo {
+    // How the game ended, if it did
+    pub result: Option<GameResult>,
+    pub termination: Option<Termination>,
+    // Whether the episode was cut short by the step limit
+    pub truncated: bool,
+    pub steps: usize,
+}
+
+// An observation, the reward, whether the episode is over, and details
+pub type Step = (Observation, f64, bool, StepInfo);
+
+// Without an opponent, both sides are played through `step`, and rewards
+// go to whichever side moved. With one, the agent plays the side to move
+// when the episode starts and the opponent replies within each step.
+pub struct ChessEnv {
+    state: GameState,
+    opponent: Option<Box<dyn Agent>>,
+    reward_scheme: RewardScheme,
+    max_steps: usize,
+    opening_plies: usize,
+    random: Random,
+    steps: usize,
+    done: bool,
+    // Keys of earlier positions, and plies since a capture or pawn move,
+    // for the draw rules
+    keys: Vec<u64>,
+    halfmove_clock: usize,
+}
+
+impl Default for ChessEnv {
+    fn default() -> ChessEnv {
+        ChessEnv::new()
+    }
+}
+
+impl ChessEnv {
+    pub fn new() -> ChessEnv {
+        ChessEnv {
+            state: GameState::new(),
+            opponent: None,
+            reward_scheme: RewardScheme::default(),
+            max_steps: DEFAULT_MAX_PLIES,
+            opening_plies: 0,
+            random: Random::new(0),
+            steps: 0,
+            done: false,
+            keys: vec![],
+            halfmove_clock: 0,
+        }
+    }
+
+    pub fn with_reward_scheme(mut self, scheme: RewardScheme) -> ChessEnv {
+        self.reward_scheme = scheme;
+        self
+    }
+
+    pub fn with_opponent(mut self, opponent: Box<dyn Agent>) -> ChessEnv {
+        self.opponent = Some(opponent);
+        self
+    }
+
+    // End episodes as truncated after this many steps
+    pub fn with_max_steps(mut self, steps: usize) -> ChessEnv {
+        self.max_steps = steps;
+        self
+    }
+
+    // Start episodes after this many random moves from the initial
+    // position, chosen by the seed given to reset
+    pub fn with_random_opening(mut self, plies: usize) -> ChessEnv {
+        self.opening_plies = plies;
+        self
+    }
+
+    // Start a new episode from the initial position, or from a random
+    // opening if one is set
+    pub fn reset(&mut self, seed: Option<u64>) -> Observation {
+        if let Some(seed) = seed {
+            self.random = Random::new(seed);
+        }
+        self.start(GameState::new());
+        for _ in 0..self.opening_plies {
+            if self.done {
+                break;
+            }
+            let moves = legal_chess_moves(&self.state);
+            let action = moves[self.random.below(moves.len())];
+            // A game over before it starts can only be reset
+            self.done = self.play(&action).is_some();
+        }
+        self.observation()
+    }
+
+    // Start a new episode from a position in Forsyth-Edwards Notation
+    pub fn reset_to_fen(&mut self, fen: &str) -> Result<Observation, FenError> {
+        let state = GameState::from_fen(fen)?;
+        self.start(state);
+        Ok(self.observation())
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn legal_mask(&self) -> Vec<bool> {
+        legal_action_mask(&self.state)
+    }
+
+    pub fn step(&mut self, action: usize) -> Result<Step, EnvironmentError> {
+        if self.done {
+            return Err(EnvironmentError::EpisodeOver);
+        }
+        let chosen = index_to_action(&self.state, action)
+            .ok_or(EnvironmentError::IllegalAction(action))?;
+
+        let before = self.state;
+        let mover = before.to_move;
+        let mut ending = self.play(&chosen);
+        if ending.is_none() {
+            if let Some(opponent) = self.opponent.as_mut() {
+                let reply = opponent.select_action(&self.state);
+                ending = self.play(&reply);
+            }
+        }
+        self.steps += 1;
+
+        let truncated = ending.is_none() && self.steps >= self.max_steps;
+        self.done = ending.is_some() || truncated;
+
+        let scheme = self.reward_scheme;
+        let mut reward = match ending {
+            None => 0.0,
+            Some((GameResult::Draw, _)) => scheme.draw,
+            Some((GameResult::WhiteWins, _)) if mover == White => scheme.win,
+            Some((GameResult::BlackWins, _)) if mover == Black => scheme.win,
+            Some(_) => scheme.loss,
+        };
+        let gained = material_for(&self.state, mover) - material_for(&before, mover);
+        reward += scheme.material * gained as f64;
+
+        let info = StepInfo {
+            result: ending.map(|(result, _)| result),
+            termination: ending.map(|(_, termination)| termination),
+            truncated,
+            steps: self.steps,
+        };
+        Ok((self.observation(), reward, self.done, info))
+    }
+
+    // Make a move, and return the result if it ends the game
+    fn play(&mut self, action: &ChessMove) -> Option<(GameResult, Termination)> {
+        let before = self.state;
+        self.halfmove_clock = if is_irreversible(&before, action) { 0 } else { self.halfmove_clock + 1 };
+        self.keys.push(before.hash());
+        self.state = action.apply(&before);
+        outcome(&self.state, &self.keys, self.halfmove_clock)
+    }
+
+    fn start(&mut self, state: GameState) {
+        self.state = state;
+        self.steps = 0;
+        self.keys.clear();
+        self.halfmove_clock = 0;
+        self.done = outcome(&state, &self.keys, 0).is_some();
+    }
+
+    fn observation(&self) -> Observation {
+        Observation { state: self.state, legal_mask: self.legal_mask() }
+    }
+}
+
+// A side's material less its opponent's, in pawns
+fn material_for(state: &GameState, color: Color) -> i32 {
+    let (white, black) = relative_material_values(state);
+    match color {
+        White => white as i32 - black as i32,
+        Black => black as i32 - white as i32,
+    }
+}
@@ -0,0 +1,712 @@
+// The single responsibility of this module is to estimate how good a
+// position is without searching any further.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    Piece,
+    PieceName::{Pawn, Knight, Bishop, Rook, Queen, King},
+    Color,
+    Color::{White, Black},
+};
+
+use crate::utilities::relative_material_values;
+
+use std::path::Path;
+
+// Scores positions in centipawns, from the perspective of the player to
+// move. Implementations must be cheap enough to call at every leaf of a
+// search, and must not look for checkmate or stalemate, which the search
+// detects itself.
+pub trait Evaluator: Send + Sync {
+    fn evaluate(&self, state: &GameState) -> i32;
+
+    // Evaluators that can reuse work between a position and the positions
+    // that follow it return a tracker, which the search keeps informed of
+    // every move it makes and unmakes
+    fn tracker(&self) -> Option<Box<dyn EvaluationTracker>> {
+        None
+    }
+}
+
+// Follows one line of play at a time. After reset, every make has to be
+// matched by an unmake in reverse order, like a stack. evaluate must agree
+// with the Evaluator that created the tracker for the position on top.
+pub trait EvaluationTracker: Send {
+    fn reset(&mut self, state: &GameState);
+    // `after` follows `before` by a move, or by passing the turn
+    fn make(&mut self, before: &GameState, after: &GameState);
+    fn unmake(&mut self);
+    fn evaluate(&self, state: &GameState) -> i32;
+}
+
+// Counts material and nothing else
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct MaterialEvaluator;
+
+impl Evaluator for MaterialEvaluator {
+    fn evaluate(&self, state: &GameState) -> i32 {
+        let (white, black) = relative_material_values(state);
+        let balance = 100 * (white as i32 - black as i32);
+        match state.to_move {
+            White => balance,
+            Black => -balance,
+        }
+    }
+}
+
+// How much each piece contributes to the game phase. A position with all
+// of the starting pieces is pure middlegame, one with only kings and pawns
+// is pure endgame, and everything in between is a blend of the two.
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Every term of the classical evaluation. Pairs hold a middlegame value
+// followed by an endgame value. Arrays indexed by piece are ordered pawn,
+// knight, bishop, rook, queen, king, and piece-square tables are written
+// from white's point of view, starting at a8 and ending at h1, so that
+// they look like a board diagram.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct EvaluationWeights {
+    pub middlegame_values: [i32; 6],
+    pub endgame_values: [i32; 6],
+    pub middlegame_tables: [[i32; 64]; 6],
+    pub endgame_tables: [[i32; 64]; 6],
+    // Per square a knight, bishop, rook or queen can move to, not counting
+    // squares guarded by enemy pawns
+    pub mobility: [(i32, i32); 4],
+    pub doubled_pawn: (i32, i32),
+    pub isolated_pawn: (i32, i32),
+    // Indexed by how many ranks the pawn has advanced
+    pub passed_pawn: [(i32, i32); 8],
+    // Per friendly pawn on the three files around the king, one or two
+    // ranks in front of it
+    pub pawn_shield: [i32; 2],
+    // Per square next to the king attacked by an enemy knight, bishop,
+    // rook or queen. Only applies in the middlegame.
+    pub king_attack: [i32; 4],
+    pub bishop_pair: (i32, i32),
+}
+
+impl Default for EvaluationWeights {
+    fn default() -> EvaluationWeights {
+        EvaluationWeights {
+            middlegame_values: [100, 320, 330, 480, 950, 0],
+            endgame_values: [120, 290, 310, 520, 940, 0],
+            middlegame_tables: [
+                [
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                     40,  40,  40,  40,  40,  40,  40,  40,
+                     10,  10,  20,  30,  30,  20,  10,  10,
+                      5,   5,  10,  25,  25,  10,   5,   5,
+                      0,   0,   0,  20,  20,   0,   0,   0,
+                      5,  -5, -10,   0,   0, -10,  -5,   5,
+                      5,  10,  10, -20, -20,  10,  10,   5,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                ],
+                [
+                    -50, -40, -30, -30, -30, -30, -40, -50,
+                    -40, -20,   0,   0,   0,   0, -20, -40,
+                    -30,   0,  10,  15,  15,  10,   0, -30,
+                    -30,   5,  15,  20,  20,  15,   5, -30,
+                    -30,   0,  15,  20,  20,  15,   0, -30,
+                    -30,   5,  10,  15,  15,  10,   5, -30,
+                    -40, -20,   0,   5,   5,   0, -20, -40,
+                    -50, -40, -30, -30, -30, -30, -40, -50,
+                ],
+                [
+                    -20, -10, -10, -10, -10, -10, -10, -20,
+                    -10,   0,   0,   0,   0,   0,   0, -10,
+                    -10,   0,   5,  10,  10,   5,   0, -10,
+                    -10,   5,   5,  10,  10,   5,   5, -10,
+                    -10,   0,  10,  10,  10,  10,   0, -10,
+                    -10,  10,  10,  10,  10,  10,  10, -10,
+                    -10,   5,   0,   0,   0,   0,   5, -10,
+                    -20, -10, -10, -10, -10, -10, -10, -20,
+                ],
+                [
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      5,  10,  10,  10,  10,  10,  10,   5,
+                     -5,   0,   0,   0,   0,   0,   0,  -5,
+                     -5,   0,   0,   0,   0,   0,   0,  -5,
+                     -5,   0,   0,   0,   0,   0,   0,  -5,
+                     -5,   0,   0,   0,   0,   0,   0,  -5,
+                     -5,   0,   0,   0,   0,   0,   0,  -5,
+                      0,   0,   0,   5,   5,   0,   0,   0,
+                ],
+                [
+                    -20, -10, -10,  -5,  -5, -10, -10, -20,
+                    -10,   0,   0,   0,   0,   0,   0, -10,
+                    -10,   0,   5,   5,   5,   5,   0, -10,
+                     -5,   0,   5,   5,   5,   5,   0,  -5,
+                      0,   0,   5,   5,   5,   5,   0,  -5,
+                    -10,   5,   5,   5,   5,   5,   0, -10,
+                    -10,   0,   5,   0,   0,   0,   0, -10,
+                    -20, -10, -10,  -5,  -5, -10, -10, -20,
+                ],
+                [
+                    -30, -40, -40, -50, -50, -40, -40, -30,
+                    -30, -40, -40, -50, -50, -40, -40, -30,
+                    -30, -40, -40, -50, -50, -40, -40, -30,
+                    -30, -40, -40, -50, -50, -40, -40, -30,
+                    -20, -30, -30, -40, -40, -30, -30, -20,
+                    -10, -20, -20, -20, -20, -20, -20, -10,
+                     20,  20,   0,   0,   0,   0,  20,  20,
+                     20,  30,  10,   0,   0,  10,  30,  20,
+                ],
+            ],
+            endgame_tables: [
+                [
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                     30,  30,  30,  30,  30,  30,  30,  30,
+                     20,  20,  20,  20,  20,  20,  20,  20,
+                     10,  10,  10,  10,  10,  10,  10,  10,
+                      5,   5,   5,   5,   5,   5,   5,   5,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                ],
+                [
+                    -50, -40, -30, -30, -30, -30, -40, -50,
+                    -40, -20,   0,   0,   0,   0, -20, -40,
+                    -30,   0,  10,  15,  15,  10,   0, -30,
+                    -30,   5,  15,  20,  20,  15,   5, -30,
+                    -30,   0,  15,  20,  20,  15,   0, -30,
+                    -30,   5,  10,  15,  15,  10,   5, -30,
+                    -40, -20,   0,   5,   5,   0, -20, -40,
+                    -50, -40, -30, -30, -30, -30, -40, -50,
+                ],
+                [
+                    -20, -10, -10, -10, -10, -10, -10, -20,
+                    -10,   0,   0,   0,   0,   0,   0, -10,
+                    -10,   0,   5,  10,  10,   5,   0, -10,
+                    -10,   0,  10,  15,  15,  10,   0, -10,
+                    -10,   0,  10,  15,  15,  10,   0, -10,
+                    -10,   0,   5,  10,  10,   5,   0, -10,
+                    -10,   0,   0,   0,   0,   0,   0, -10,
+                    -20, -10, -10, -10, -10, -10, -10, -20,
+                ],
+                [
+                      5,   5,   5,   5,   5,   5,   5,   5,
+                     10,  10,  10,  10,  10,  10,  10,  10,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                      0,   0,   0,   0,   0,   0,   0,   0,
+                ],
+                [
+                    -20, -10, -10,  -5,  -5, -10, -10, -20,
+                    -10,   0,   5,   5,   5,   5,   0, -10,
+                    -10,   5,  10,  10,  10,  10,   5, -10,
+                     -5,   5,  10,  15,  15,  10,   5,  -5,
+                     -5,   5,  10,  15,  15,  10,   5,  -5,
+                    -10,   5,  10,  10,  10,  10,   5, -10,
+                    -10,   0,   5,   5,   5,   5,   0, -10,
+                    -20, -10, -10,  -5,  -5, -10, -10, -20,
+                ],
+                [
+                    -50, -40, -30, -20, -20, -30, -40, -50,
+                    -30, -20, -10,   0,   0, -10, -20, -30,
+                    -30, -10,  20,  30,  30,  20, -10, -30,
+                    -30, -10,  30,  40,  40,  30, -10, -30,
+                    -30, -10,  30,  40,  40,  30, -10, -30,
+                    -30, -10,  20,  30,  30,  20, -10, -30,
+                    -30, -30,   0,   0,   0,   0, -30, -30,
+                    -50, -30, -30, -30, -30, -30, -30, -50,
+                ],
+            ],
+            mobility: [(4, 4), (5, 5), (2, 4), (1, 2)],
+            doubled_pawn: (-10, -20),
+            isolated_pawn: (-10, -15),
+            passed_pawn: [
+                (0, 0),
+                (0, 5),
+                (5, 10),
+                (10, 20),
+                (20, 40),
+                (35, 70),
+                (60, 110),
+                (0, 0),
+            ],
+            pawn_shield: [12, 6],
+            king_attack: [8, 6, 10, 15],
+            bishop_pair: (30, 50),
+        }
+    }
+}
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// Weights files are plain text with one term per line: the name of a
+// field of EvaluationWeights followed by its values separated by spaces.
+// Pairs are written middlegame value first, and each piece-square table
+// gets its own line, e.g. "middlegame_table_knight -50 -40 ...". Blank
+// lines and lines starting with '#' are ignored, and terms missing from
+// the file keep their default values.
+impl EvaluationWeights {
+    // Every weight as a single list, in the order of a weights file
+    pub fn parameters(&self) -> Vec<i32> {
+        self.terms().into_iter().flat_map(|(_, values)| values).collect()
+    }
+
+    // The inverse of parameters. Panics if `parameters` is the wrong length.
+    pub fn set_parameters(&mut self, parameters: &[i32]) {
+        let names: Vec<(String, usize)> = self.terms()
+            .into_iter()
+            .map(|(name, values)| (name, values.len()))
+            .collect();
+        let total: usize = names.iter().map(|(_, count)| count).sum();
+        assert_eq!(total, parameters.len(), "wrong number of evaluation parameters");
+
+        let mut start = 0;
+        for (name, count) in names.iter() {
+            self.set_term(name, &parameters[start..start + count]).unwrap();
+            start += count;
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+        for (name, values) in self.terms() {
+            output.push_str(&name);
+            for value in values.iter() {
+                output.push_str(&format!(" {}", value));
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    pub fn from_text(text: &str) -> Result<EvaluationWeights, WeightsError> {
+        let mut weights = EvaluationWeights::default();
+        for line in text.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let name = fields.next().unwrap();
+            let mut values = vec![];
+            for field in fields {
+                match field.parse::<i32>() {
+                    Ok(value) => values.push(value),
+                    Err(_) => return Err(WeightsError::InvalidValue(field.to_string())),
+                }
+            }
+            weights.set_term(name, &values)?;
+        }
+        Ok(weights)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EvaluationWeights, WeightsError> {
+        let text = std::fs::read_to_string(path).map_err(WeightsError::Io)?;
+        EvaluationWeights::from_text(&text)
+    }
+
+    fn terms(&self) -> Vec<(String, Vec<i32>)> {
+        let mut terms = vec![
+            ("middlegame_values".to_string(), self.middlegame_values.to_vec()),
+            ("endgame_values".to_string(), self.endgame_values.to_vec()),
+        ];
+        for (piece, table) in PIECE_NAMES.iter().zip(self.middlegame_tables.iter()) {
+            terms.push((format!("middlegame_table_{}", piece), table.to_vec()));
+        }
+        for (piece, table) in PIECE_NAMES.iter().zip(self.endgame_tables.iter()) {
+            terms.push((format!("endgame_table_{}", piece), table.to_vec()));
+        }
+        terms.push(("mobility".to_string(), flatten_pairs(&self.mobility)));
+        terms.push(("doubled_pawn".to_string(), flatten_pairs(&[self.doubled_pawn])));
+        terms.push(("isolated_pawn".to_string(), flatten_pairs(&[self.isolated_pawn])));
+        terms.push(("passed_pawn".to_string(), flatten_pairs(&self.passed_pawn)));
+        terms.push(("pawn_shield".to_string(), self.pawn_shield.to_vec()));
+        terms.push(("king_attack".to_string(), self.king_attack.to_vec()));
+        terms.push(("bishop_pair".to_string(), flatten_pairs(&[self.bishop_pair])));
+        terms
+    }
+
+    fn set_term(&mut self, name: &str, values: &[i32]) -> Result<(), WeightsError> {
+        let expected = match self.terms().into_iter().find(|(term, _)| term == name) {
+            Some((_, current)) => current.len(),
+            None => return Err(WeightsError::UnknownTerm(name.to_string())),
+        };
+        if values.len() != expected {
+            return Err(WeightsError::WrongValueCount(name.to_string(), values.len()));
+        }
+
+        let table_piece = |prefix: &str| {
+            name.strip_prefix(prefix)
+                .and_then(|piece| PIECE_NAMES.iter().position(|name| *name == piece))
+        };
+
+        match name {
+            "middlegame_values" => self.middlegame_values.copy_from_slice(values),
+            "endgame_values" => self.endgame_values.copy_from_slice(values),
+            "mobility" => set_pairs(&mut self.mobility, values),
+            "doubled_pawn" => self.doubled_pawn = (values[0], values[1]),
+            "isolated_pawn" => self.isolated_pawn = (values[0], values[1]),
+            "passed_pawn" => set_pairs(&mut self.passed_pawn, values),
+            "pawn_shield" => self.pawn_shield.copy_from_slice(values),
+            "king_attack" => self.king_attack.copy_from_slice(values),
+            "bishop_pair" => self.bishop_pair = (values[0], values[1]),
+            _ => {
+                if let Some(piece) = table_piece("middlegame_table_") {
+                    self.middlegame_tables[piece].copy_from_slice(values);
+                } else if let Some(piece) = table_piece("endgame_table_") {
+                    self.endgame_tables[piece].copy_from_slice(values);
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+fn flatten_pairs(pairs: &[(i32, i32)]) -> Vec<i32> {
+    pairs.iter().flat_map(|(middlegame, endgame)| vec![*middlegame, *endgame]).collect()
+}
+
+fn set_pairs(pairs: &mut [(i32, i32)], values: &[i32]) {
+    for (pair, values) in pairs.iter_mut().zip(values.chunks(2)) {
+        *pair = (values[0], values[1]);
+    }
+}
+
+#[derive(Debug)]
+pub enum WeightsError {
+    Io(std::io::Error),
+    UnknownTerm(String),
+    // The term and how many values were given for it
+    WrongValueCount(String, usize),
+    InvalidValue(String),
+}
+
+// A hand-written evaluation of material, piece placement, mobility, pawn
+// structure, king safety and the bishop pair. Every term has a middlegame
+// and an endgame value, which are blended according to the game phase.
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct ClassicalEvaluator {
+    weights: EvaluationWeights,
+}
+
+impl ClassicalEvaluator {
+    pub fn new(weights: EvaluationWeights) -> ClassicalEvaluator {
+        ClassicalEvaluator { weights }
+    }
+
+    // Use the weights in a file written by EvaluationWeights::save
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ClassicalEvaluator, WeightsError> {
+        EvaluationWeights::load(path).map(ClassicalEvaluator::new)
+    }
+
+    pub fn weights(&self) -> &EvaluationWeights {
+        &self.weights
+    }
+
+    // The middlegame and endgame scores of `state` from white's
+    // perspective, before they are blended
+    fn tapered_scores(&self, state: &GameState) -> (i32, i32) {
+        let board = Board::new(state);
+        let white = self.side_scores(&board, White);
+        let black = self.side_scores(&board, Black);
+        (white.0 - black.0, white.1 - black.1)
+    }
+
+    fn side_scores(&self, board: &Board, color: Color) -> (i32, i32) {
+        let weights = &self.weights;
+        let side = color_index(color);
+        let enemy = 1 - side;
+        let mut middlegame = 0;
+        let mut endgame = 0;
+
+        // Squares guarded by enemy pawns don't count towards mobility
+        let mut enemy_pawn_guards = 0u64;
+        for square in squares_of(board.pieces[enemy][0]) {
+            enemy_pawn_guards |= pawn_attacks(square, opposite(color));
+        }
+        let own = board.occupied[side];
+        let enemy_king = board.pieces[enemy][5].trailing_zeros() as usize;
+        let king_zone = if enemy_king < 64 { king_attacks(enemy_king) } else { 0 };
+
+        for kind in 0..6 {
+            for square in squares_of(board.pieces[side][kind]) {
+                let table_square = match color {
+                    White => square ^ 56,
+                    Black => square,
+                };
+                middlegame += weights.middlegame_values[kind];
+                middlegame += weights.middlegame_tables[kind][table_square];
+                endgame += weights.endgame_values[kind];
+                endgame += weights.endgame_tables[kind][table_square];
+
+                if (1..5).contains(&kind) {
+                    let attacks = piece_attacks(kind, square, board.all());
+                    let reachable = attacks & !own & !enemy_pawn_guards;
+                    let (mobility_mg, mobility_eg) = weights.mobility[kind - 1];
+                    middlegame += mobility_mg * reachable.count_ones() as i32;
+                    endgame += mobility_eg * reachable.count_ones() as i32;
+                    // Pressure on the enemy king
+                    middlegame += weights.king_attack[kind - 1]
+                        * (attacks & king_zone).count_ones() as i32;
+                }
+            }
+        }
+
+        let (pawns_mg, pawns_eg) = self.pawn_structure(board, color);
+        middlegame += pawns_mg;
+        endgame += pawns_eg;
+        middlegame += self.pawn_shield(board, color);
+
+        if board.pieces[side][2].count_ones() >= 2 {
+            middlegame += weights.bishop_pair.0;
+            endgame += weights.bishop_pair.1;
+        }
+
+        (middlegame, endgame)
+    }
+
+    fn pawn_structure(&self, board: &Board, color: Color) -> (i32, i32) {
+        let weights = &self.weights;
+        let pawns = board.pieces[color_index(color)][0];
+        let enemy_pawns = board.pieces[color_index(opposite(color))][0];
+        let mut middlegame = 0;
+        let mut endgame = 0;
+
+        for file in 0..8 {
+            let count = (pawns & file_mask(file)).count_ones() as i32;
+            if count > 1 {
+                middlegame += weights.doubled_pawn.0 * (count - 1);
+                endgame += weights.doubled_pawn.1 * (count - 1);
+            }
+            if count > 0 && pawns & adjacent_files_mask(file) == 0 {
+                middlegame += weights.isolated_pawn.0 * count;
+                endgame += weights.isolated_pawn.1 * count;
+            }
+        }
+
+        for square in squares_of(pawns) {
+            if enemy_pawns & passed_pawn_mask(square, color) == 0 {
+                let (bonus_mg, bonus_eg) = weights.passed_pawn[relative_rank(square, color)];
+                middlegame += bonus_mg;
+                endgame += bonus_eg;
+            }
+        }
+
+        (middlegame, endgame)
+    }
+
+    fn pawn_shield(&self, board: &Board, color: Color) -> i32 {
+        let side = color_index(color);
+        let king = board.pieces[side][5].trailing_zeros() as usize;
+        if king >= 64 {
+            return 0;
+        }
+        let pawns = board.pieces[side][0];
+        let forward: i32 = match color {
+            White => 8,
+            Black => -8,
+        };
+        let files = file_mask(king % 8) | adjacent_files_mask(king % 8);
+
+        let mut bonus = 0;
+        for (distance, weight) in self.weights.pawn_shield.iter().enumerate() {
+            let rank_square = king as i32 + forward * (distance as i32 + 1);
+            if !(0..64).contains(&rank_square) {
+                break;
+            }
+            let shield = pawns & files & rank_mask(rank_square as usize / 8);
+            bonus += weight * shield.count_ones() as i32;
+        }
+        bonus
+    }
+}
+
+impl Evaluator for ClassicalEvaluator {
+    fn evaluate(&self, state: &GameState) -> i32 {
+        let (middlegame, endgame) = self.tapered_scores(state);
+        let phase = game_phase(state);
+        let score = (middlegame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE;
+        match state.to_move {
+            White => score,
+            Black => -score,
+        }
+    }
+}
+
+// How far the game is from the endgame, from 0 for an endgame to
+// MAX_PHASE for the starting position. Promotions can push the sum past
+// MAX_PHASE, so it is capped.
+pub fn game_phase(state: &GameState) -> i32 {
+    let phase: i32 = state.squares
+        .iter()
+        .flatten()
+        .map(|piece| PHASE_WEIGHTS[kind_index(piece)])
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+// The position as one set of squares per color and kind of piece
+struct Board {
+    pieces: [[u64; 6]; 2],
+    occupied: [u64; 2],
+}
+
+impl Board {
+    fn new(state: &GameState) -> Board {
+        let mut board = Board { pieces: [[0; 6]; 2], occupied: [0; 2] };
+        for (square, maybe_piece) in state.squares.iter().enumerate() {
+            if let Some(piece) = maybe_piece {
+                let side = color_index(piece.color);
+                board.pieces[side][kind_index(piece)] |= 1 << square;
+                board.occupied[side] |= 1 << square;
+            }
+        }
+        board
+    }
+
+    fn all(&self) -> u64 {
+        self.occupied[0] | self.occupied[1]
+    }
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        White => 0,
+        Black => 1,
+    }
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        White => Black,
+        Black => White,
+    }
+}
+
+fn kind_index(piece: &Piece) -> usize {
+    match piece.name {
+        Pawn => 0,
+        Knight => 1,
+        Bishop => 2,
+        Rook => 3,
+        Queen => 4,
+        King => 5,
+    }
+}
+
+fn squares_of(mut set: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if set == 0 {
+            return None;
+        }
+        let square = set.trailing_zeros() as usize;
+        set &= set - 1;
+        Some(square)
+    })
+}
+
+fn relative_rank(square: usize, color: Color) -> usize {
+    match color {
+        White => square / 8,
+        Black => 7 - square / 8,
+    }
+}
+
+fn file_mask(file: usize) -> u64 {
+    0x0101_0101_0101_0101 << file
+}
+
+fn rank_mask(rank: usize) -> u64 {
+    0xff << (8 * rank)
+}
+
+fn adjacent_files_mask(file: usize) -> u64 {
+    let mut mask = 0;
+    if file > 0 {
+        mask |= file_mask(file - 1);
+    }
+    if file < 7 {
+        mask |= file_mask(file + 1);
+    }
+    mask
+}
+
+// The squares an enemy pawn would have to be on to stop the pawn on
+// `square` from promoting
+fn passed_pawn_mask(square: usize, color: Color) -> u64 {
+    let files = file_mask(square % 8) | adjacent_files_mask(square % 8);
+    let rank = square / 8;
+    let ahead = match color {
+        White if rank < 7 => !0u64 << (8 * (rank + 1)),
+        Black if rank > 0 => !0u64 >> (8 * (8 - rank)),
+        _ => 0,
+    };
+    files & ahead
+}
+
+// Every square reachable from `square` by repeating each step until the
+// edge of the board, or only once if `slides` is false. Steps are given
+// as (file, rank) offsets, and sliding stops at the first occupied square.
+fn step_attacks(square: usize, steps: &[(i32, i32)], slides: bool, occupied: u64) -> u64 {
+    let mut attacks = 0;
+    for (file_step, rank_step) in steps.iter() {
+        let mut file = (square % 8) as i32;
+        let mut rank = (square / 8) as i32;
+        loop {
+            file += file_step;
+            rank += rank_step;
+            if !(0..8).contains(&file) || !(0..8).contains(&rank) {
+                break;
+            }
+            let target = (rank * 8 + file) as u64;
+            attacks |= 1 << target;
+            if !slides || occupied & (1 << target) != 0 {
+                break;
+            }
+        }
+    }
+    attacks
+}
+
+const KNIGHT_STEPS: [(i32, i32); 8] = [
+    (1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2),
+];
+const BISHOP_STEPS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+const ROOK_STEPS: [(i32, i32); 4] = [(1, 0), (0, -1), (-1, 0), (0, 1)];
+const KING_STEPS: [(i32, i32); 8] = [
+    (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1), (0, 1), (1, 1),
+];
+
+fn pawn_attacks(square: usize, color: Color) -> u64 {
+    match color {
+        White => step_attacks(square, &[(-1, 1), (1, 1)], false, 0),
+        Black => step_attacks(square, &[(-1, -1), (1, -1)], false, 0),
+    }
+}
+
+fn king_attacks(square: usize) -> u64 {
+    step_attacks(square, &KING_STEPS, false, 0)
+}
+
+fn piece_attacks(kind: usize, square: usize, occupied: u64) -> u64 {
+    match kind {
+        1 => step_attacks(square, &KNIGHT_STEPS, false, occupied),
+        2 => step_attacks(square, &BISHOP_STEPS, true, occupied),
+        3 => step_attacks(square, &ROOK_STEPS, true, occupied),
+        4 => {
+            step_attacks(square, &BISHOP_STEPS, true, occupied)
+                | step_attacks(square, &ROOK_STEPS, true, occupied)
+        },
+        _ => king_attacks(square),
+    }
+}
@@ -0,0 +1,99 @@
+// The single responsibility of this module is to predict the material
+// outcome of a series of captures on a single square.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    PieceName,
+    PieceName::{Pawn, King},
+    Color,
+    Color::{White, Black},
+};
+
+use crate::actions::Capture;
+
+use crate::utilities::{
+    move_is_pseudo_legal,
+    movement_is_vertical,
+    piece_value,
+};
+
+// Kings can take part in an exchange, but can never be given up in one
+const KING_EXCHANGE_VALUE: i32 = 10_000;
+
+pub fn exchange_value(name: PieceName) -> i32 {
+    match name {
+        King => KING_EXCHANGE_VALUE,
+        _ => 100 * piece_value(&name) as i32,
+    }
+}
+
+// Static exchange evaluation. Play out `capture` followed by every
+// recapture on the same square, always capturing with the least valuable
+// piece available, and return the material the capturing side can expect
+// to win in centipawns. Either side may stop recapturing whenever that is
+// better for them. Pins are ignored.
+pub fn see(state: &GameState, capture: &Capture) -> i32 {
+    let target = capture.on;
+    let (victim, attacker) = match (state.squares[target], state.squares[capture.with]) {
+        (Some(victim), Some(attacker)) => (victim, attacker),
+        _ => return 0,
+    };
+
+    let mut board = *state;
+    let mut gains = vec![exchange_value(victim.name)];
+    let mut on_target = attacker.name;
+    let mut side = attacker.color;
+
+    board.squares[target] = board.squares[capture.with];
+    board.squares[capture.with] = None;
+
+    loop {
+        side = if side == White { Black } else { White };
+        let square = match least_valuable_attacker(&board, target, side) {
+            Some(square) => square,
+            None => break,
+        };
+        // What the side to move would be ahead by if it captured here
+        // and then lost the capturing piece
+        let previous = *gains.last().unwrap();
+        gains.push(exchange_value(on_target) - previous);
+
+        on_target = board.squares[square].unwrap().name;
+        board.squares[target] = board.squares[square];
+        board.squares[square] = None;
+    }
+
+    // Work backwards, letting each side decline a losing recapture
+    for i in (1..gains.len()).rev() {
+        gains[i - 1] = -(-gains[i - 1]).max(gains[i]);
+    }
+    gains[0]
+}
+
+// Find the cheapest piece of `color` that attacks `target`, which is
+// occupied by a piece of the other color
+fn least_valuable_attacker(state: &GameState, target: usize, color: Color) -> Option<usize> {
+    let mut best: Option<(i32, usize)> = None;
+
+    for (square, maybe_piece) in state.squares.iter().enumerate() {
+        let piece = match maybe_piece {
+            Some(piece) if piece.color == color => piece,
+            _ => continue,
+        };
+        // Pawns only capture diagonally
+        if piece.name == Pawn && movement_is_vertical(square, target) {
+            continue;
+        }
+        if !move_is_pseudo_legal(square, target, state) {
+            continue;
+        }
+        let value = exchange_value(piece.name);
+        match best {
+            Some((best_value, _)) if best_value <= value => (),
+            _ => best = Some((value, square)),
+        }
+    }
+
+    best.map(|(_, square)| square)
+}
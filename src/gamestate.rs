@@ -4,6 +4,13 @@ use crate::pieces::{
     Color,
 };
 
+use crate::rendering::TextRenderer;
+
+use crate::notation::{
+    fen_notation,
+    square_algebraic_to_index,
+};
+
 #[derive(Copy)]
 #[derive(Clone)]
 pub struct GameState {
@@ -92,18 +99,188 @@ impl GameState {
 
         state
     }
+
+    // Read the 8x8 grid printed by to_string, ranks 8 through 1 from top to
+    // bottom. An optional line after the grid gives the side to move and
+    // castling rights in FEN style, e.g. "b KQkq" or "w -". Without it,
+    // white is to move and nobody can castle.
+    pub fn from_diagram(diagram: &str) -> Result<GameState, DiagramError> {
+        let lines: Vec<&str> = diagram
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        if lines.len() < 8 || lines.len() > 9 {
+            return Err(DiagramError::WrongRankCount(lines.len()));
+        }
+
+        let mut state = GameState::with_placements(vec![]);
+
+        for (row, line) in lines[..8].iter().enumerate() {
+            let rank = 7 - row;
+            let symbols: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+            if symbols.len() != 8 {
+                return Err(DiagramError::WrongFileCount(rank + 1, symbols.len()));
+            }
+            for (file, symbol) in symbols.iter().enumerate() {
+                if *symbol == '.' {
+                    continue;
+                }
+                match Piece::from_char(*symbol) {
+                    Some(piece) => state.squares[8 * rank + file] = Some(piece),
+                    None => return Err(DiagramError::UnknownSymbol(*symbol)),
+                }
+            }
+        }
+
+        if let Some(annotation) = lines.get(8) {
+            parse_diagram_annotation(annotation, &mut state)?;
+        }
+
+        Ok(state)
+    }
 }
 
-impl std::fmt::Debug for GameState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut output = "".to_string();
+impl GameState {
+    // Read a position in Forsyth-Edwards Notation. The halfmove and
+    // fullmove clocks are optional, and are ignored because GameState
+    // doesn't track them.
+    pub fn from_fen(fen: &str) -> Result<GameState, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+
+        let mut state = GameState::with_placements(vec![]);
 
-        for i in 0..self.squares.len() {
-            let square_str = format!("\n{} {:?}", i, self.squares[i]);
-            output.push_str(&square_str);
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        for (row, symbols) in ranks.iter().enumerate() {
+            let rank = 7 - row;
+            let mut file = 0;
+            for symbol in symbols.chars() {
+                if let Some(skip) = symbol.to_digit(10) {
+                    file += skip as usize;
+                    continue;
+                }
+                let piece = Piece::from_char(symbol).ok_or(FenError::UnknownSymbol(symbol))?;
+                if file < 8 {
+                    state.squares[8 * rank + file] = Some(piece);
+                }
+                file += 1;
+            }
+            if file != 8 {
+                return Err(FenError::WrongFileCount(rank + 1, file));
+            }
         }
 
-        write!(f, "{}", output)
+        state.to_move = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => return Err(FenError::InvalidSideToMove(other.to_string())),
+        };
+
+        if fields[2] != "-" {
+            for right in fields[2].chars() {
+                match right {
+                    'K' => state.white_can_castle_kingside = true,
+                    'Q' => state.white_can_castle_queenside = true,
+                    'k' => state.black_can_castle_kingside = true,
+                    'q' => state.black_can_castle_queenside = true,
+                    _ => return Err(FenError::InvalidCastlingRights(fields[2].to_string())),
+                }
+            }
+        }
+
+        if fields[3] != "-" {
+            // Behind a pawn that just moved two squares, so on the third
+            // rank from the side that moved
+            let rank = match state.to_move {
+                Color::White => 5,
+                Color::Black => 2,
+            };
+            match square_algebraic_to_index(fields[3]) {
+                Some(square) if square / 8 == rank => state.en_passant_square = Some(square),
+                _ => return Err(FenError::InvalidEnPassantSquare(fields[3].to_string())),
+            }
+        }
+
+        for clock in fields[4..].iter() {
+            if clock.parse::<u32>().is_err() {
+                return Err(FenError::InvalidClock(clock.to_string()));
+            }
+        }
+
+        Ok(state)
+    }
+}
+
+fn parse_diagram_annotation(annotation: &str, state: &mut GameState) -> Result<(), DiagramError> {
+    let invalid = || DiagramError::InvalidAnnotation(annotation.to_string());
+    let fields: Vec<&str> = annotation.split_whitespace().collect();
+
+    match fields.first() {
+        Some(&"w") => state.to_move = Color::White,
+        Some(&"b") => state.to_move = Color::Black,
+        _ => return Err(invalid()),
+    }
+
+    match fields.get(1) {
+        None | Some(&"-") => (),
+        Some(rights) => {
+            for right in rights.chars() {
+                match right {
+                    'K' => state.white_can_castle_kingside = true,
+                    'Q' => state.white_can_castle_queenside = true,
+                    'k' => state.black_can_castle_kingside = true,
+                    'q' => state.black_can_castle_queenside = true,
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+    }
+
+    if fields.len() > 2 {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum DiagramError {
+    // The number of lines found, when they aren't 8 ranks and an optional
+    // annotation
+    WrongRankCount(usize),
+    // The rank number and how many squares it held
+    WrongFileCount(usize, usize),
+    UnknownSymbol(char),
+    InvalidAnnotation(String),
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum FenError {
+    // The number of space separated fields, when it isn't 4 to 6
+    WrongFieldCount(usize),
+    // The number of ranks found, when it isn't 8
+    WrongRankCount(usize),
+    // The rank number and how many squares it described
+    WrongFileCount(usize, usize),
+    UnknownSymbol(char),
+    InvalidSideToMove(String),
+    InvalidCastlingRights(String),
+    InvalidEnPassantSquare(String),
+    InvalidClock(String),
+}
+
+impl std::fmt::Debug for GameState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let board = TextRenderer::new().coordinates(true).render(self);
+        write!(f, "\n{}{}", board, fen_notation(self))
     }
 }
 
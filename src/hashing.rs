@@ -0,0 +1,122 @@
+// The single responsibility of this module is to reduce GameStates to
+// 64 bit Zobrist keys, so that equal positions can be recognized cheaply.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    Piece,
+    PieceName,
+    Color,
+};
+
+// Every feature of a position gets its own random key, and a position's
+// hash is the xor of the keys of the features it has
+struct ZobristKeys {
+    pieces: [[u64; 64]; 12],
+    black_to_move: u64,
+    castling: [u64; 4],
+    en_passant_file: [u64; 8],
+}
+
+const KEYS: ZobristKeys = generate_keys();
+
+// SplitMix64, which is good enough for hashing and can run at compile time
+pub(crate) const fn next_random(seed: u64) -> (u64, u64) {
+    let seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (seed, z ^ (z >> 31))
+}
+
+const fn generate_keys() -> ZobristKeys {
+    let mut keys = ZobristKeys {
+        pieces: [[0; 64]; 12],
+        black_to_move: 0,
+        castling: [0; 4],
+        en_passant_file: [0; 8],
+    };
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    let mut random;
+
+    let mut piece = 0;
+    while piece < 12 {
+        let mut square = 0;
+        while square < 64 {
+            (seed, random) = next_random(seed);
+            keys.pieces[piece][square] = random;
+            square += 1;
+        }
+        piece += 1;
+    }
+
+    (seed, random) = next_random(seed);
+    keys.black_to_move = random;
+
+    let mut i = 0;
+    while i < 4 {
+        (seed, random) = next_random(seed);
+        keys.castling[i] = random;
+        i += 1;
+    }
+
+    let mut file = 0;
+    while file < 8 {
+        (seed, random) = next_random(seed);
+        keys.en_passant_file[file] = random;
+        file += 1;
+    }
+
+    keys
+}
+
+pub(crate) fn piece_index(piece: &Piece) -> usize {
+    let name = match piece.name {
+        PieceName::Pawn => 0,
+        PieceName::Knight => 1,
+        PieceName::Bishop => 2,
+        PieceName::Rook => 3,
+        PieceName::Queen => 4,
+        PieceName::King => 5,
+    };
+    match piece.color {
+        Color::White => name,
+        Color::Black => name + 6,
+    }
+}
+
+impl GameState {
+    // A key that is equal for equal positions, and almost certainly
+    // different for different ones
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for (square, maybe_piece) in self.squares.iter().enumerate() {
+            if let Some(piece) = maybe_piece {
+                hash ^= KEYS.pieces[piece_index(piece)][square];
+            }
+        }
+
+        if self.to_move == Color::Black {
+            hash ^= KEYS.black_to_move;
+        }
+
+        let rights = [
+            self.white_can_castle_kingside,
+            self.white_can_castle_queenside,
+            self.black_can_castle_kingside,
+            self.black_can_castle_queenside,
+        ];
+        for (i, right) in rights.iter().enumerate() {
+            if *right {
+                hash ^= KEYS.castling[i];
+            }
+        }
+
+        if let Some(square) = self.en_passant_square {
+            hash ^= KEYS.en_passant_file[square % 8];
+        }
+
+        hash
+    }
+}
@@ -4,10 +4,31 @@ mod pieces;
 mod notation;
 mod gamestate;
 mod utilities;
+mod validation;
+mod builder;
+mod rendering;
+mod svg;
+mod search;
+mod hashing;
+mod transposition;
+mod exchange;
+mod evaluation;
+mod tuner;
+mod nnue;
+mod uci;
+mod cecp;
+mod statistics;
+mod arena;
+mod agents;
+mod environment;
+mod policy;
+mod planes;
+mod mcts;
 mod tests;
 
 pub use utilities::{
     legal_actions,
+    legal_chess_moves,
     legal_next_states,
     relative_material_values,
     is_checkmate,
@@ -22,3 +43,41 @@ pub use pieces::*;
 
 pub use notation::*;
 
+pub use validation::*;
+
+pub use builder::*;
+
+pub use rendering::*;
+
+pub use svg::*;
+
+pub use search::*;
+
+pub use transposition::*;
+
+pub use exchange::*;
+
+pub use evaluation::*;
+
+pub use tuner::*;
+
+pub use nnue::*;
+
+pub use uci::*;
+
+pub use cecp::*;
+
+pub use statistics::*;
+
+pub use arena::*;
+
+pub use agents::*;
+
+pub use environment::*;
+
+pub use policy::*;
+
+pub use planes::*;
+
+pub use mcts::*;
+
@@ -0,0 +1,519 @@
+// The single responsibility of this module is to search positions by
+// Monte Carlo tree search in the style of AlphaZero, guided by priors and
+// values from an evaluator such as a neural network.
+
+use crate::gamestate::GameState;
+
+use crate::actions::{
+    Action,
+    ChessMove,
+};
+
+use crate::utilities::{
+    color_is_checked,
+    legal_chess_moves,
+};
+
+use crate::arena::insufficient_material;
+
+use crate::agents::{
+    Agent,
+    Random,
+};
+
+use crate::policy::{
+    PolicyOrientation,
+    POLICY_SIZE,
+    move_to_policy_index,
+};
+
+// Priors and a value for positions that aren't over. Priors are indexed
+// as policy.rs numbers moves from the side to move's point of view, and
+// needn't be normalised or zero for illegal moves. The value is the side
+// to move's expected result, from -1 for a loss to 1 for a win.
+pub trait PolicyValueEvaluator {
+    fn evaluate(&mut self, state: &GameState) -> (Vec<f32>, f32);
+
+    // Evaluate several positions at once, as a network would in a batch
+    fn evaluate_batch(&mut self, states: &[GameState]) -> Vec<(Vec<f32>, f32)> {
+        states.iter().map(|state| self.evaluate(state)).collect()
+    }
+}
+
+impl<F: FnMut(&GameState) -> (Vec<f32>, f32)> PolicyValueEvaluator for F {
+    fn evaluate(&mut self, state: &GameState) -> (Vec<f32>, f32) {
+        self(state)
+    }
+}
+
+// Every move equally likely and every position even, which leaves the
+// search to find what it can from the ends of games alone
+pub struct UniformEvaluator;
+
+impl PolicyValueEvaluator for UniformEvaluator {
+    fn evaluate(&mut self, _state: &GameState) -> (Vec<f32>, f32) {
+        (vec![1.0; POLICY_SIZE], 0.0)
+    }
+}
+
+struct Node {
+    state: GameState,
+    // The move that led here, and the node it was played from
+    action: Option<ChessMove>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    prior: f32,
+    visits: u32,
+    // Values for the side that moved into this node, summed over visits
+    value_sum: f32,
+    // Simulations in flight through this node, waiting to be evaluated
+    virtual_visits: u32,
+    expanded: bool,
+    // The value for the side to move, if the game is over here
+    terminal: Option<f32>,
+}
+
+impl Node {
+    fn new(state: GameState, action: Option<ChessMove>, parent: Option<usize>, prior: f32) -> Node {
+        Node {
+            state,
+            action,
+            parent,
+            children: vec![],
+            prior,
+            visits: 0,
+            value_sum: 0.0,
+            virtual_visits: 0,
+            expanded: false,
+            terminal: None,
+        }
+    }
+}
+
+// A search tree rooted at the current position. Children are chosen by
+// PUCT, balancing their mean value against their prior and how rarely
+// they've been visited. The tree is kept between moves, so that the part
+// of it still reachable isn't searched again.
+//
+// Positions repeating one earlier in the tree are scored as draws, as are
+// those without mating material. The fifty move rule isn't applied.
+pub struct Mcts<E: PolicyValueEvaluator> {
+    evaluator: E,
+    nodes: Vec<Node>,
+    simulations: usize,
+    exploration: f32,
+    // Concentration and weight of noise mixed into the root's priors
+    noise: Option<(f64, f64)>,
+    root_noised: bool,
+    batch_size: usize,
+    virtual_loss: f32,
+    temperature: f64,
+    random: Random,
+}
+
+impl<E: PolicyValueEvaluator> Mcts<E> {
+    // 800 simulations a move, as AlphaZero played, one at a time and
+    // without noise, from the initial position
+    pub fn new(evaluator: E) -> Mcts<E> {
+        Mcts {
+            evaluator,
+            nodes: vec![Node::new(GameState::new(), None, None, 1.0)],
+            simulations: 800,
+            exploration: 1.25,
+            noise: None,
+            root_noised: false,
+            batch_size: 1,
+            virtual_loss: 1.0,
+            temperature: 0.0,
+            random: Random::new(0),
+        }
+    }
+
+    pub fn with_simulations(mut self, simulations: usize) -> Mcts<E> {
+        self.simulations = simulations;
+        self
+    }
+
+    // The weight of priors against values in PUCT
+    pub fn with_exploration(mut self, exploration: f32) -> Mcts<E> {
+        self.exploration = exploration;
+        self
+    }
+
+    // Mix Dirichlet noise into the root's priors, so that self-play tries
+    // moves the priors would overlook. AlphaZero used an alpha of 0.3 and
+    // a weight of 0.25.
+    pub fn with_dirichlet_noise(mut self, alpha: f64, weight: f64) -> Mcts<E> {
+        self.noise = Some((alpha, weight));
+        self
+    }
+
+    // Evaluate up to this many leaves at once. Each simulation waiting for
+    // its evaluation counts as `virtual_loss` lost for the side to move
+    // along its path, steering the rest of the batch elsewhere.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Mcts<E> {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_virtual_loss(mut self, virtual_loss: f32) -> Mcts<E> {
+        self.virtual_loss = virtual_loss;
+        self
+    }
+
+    // The temperature moves are chosen with when playing as an agent
+    pub fn with_temperature(mut self, temperature: f64) -> Mcts<E> {
+        self.temperature = temperature;
+        self
+    }
+
+    // Seeds the noise and the choice of moves
+    pub fn with_seed(mut self, seed: u64) -> Mcts<E> {
+        self.random = Random::new(seed);
+        self
+    }
+
+    pub fn root_state(&self) -> &GameState {
+        &self.nodes[0].state
+    }
+
+    pub fn root_visits(&self) -> u32 {
+        self.nodes[0].visits
+    }
+
+    // The side to move's mean value over the search, from -1 to 1
+    pub fn root_value(&self) -> f32 {
+        let root = &self.nodes[0];
+        if root.visits == 0 {
+            return 0.0;
+        }
+        -root.value_sum / root.visits as f32
+    }
+
+    // Search from this position, keeping the tree if it's already the
+    // root or one or two moves on from it
+    pub fn set_position(&mut self, state: &GameState) {
+        let key = state.hash();
+        if self.nodes[0].state.hash() == key {
+            return;
+        }
+        let mut reachable = self.nodes[0].children.clone();
+        for child in self.nodes[0].children.iter() {
+            reachable.extend(self.nodes[*child].children.iter());
+        }
+        match reachable.into_iter().find(|node| self.nodes[*node].state.hash() == key) {
+            Some(node) => self.reroot(node),
+            None => self.reset(*state),
+        }
+    }
+
+    // Play a move from the root, keeping what was searched after it
+    pub fn advance(&mut self, action: &ChessMove) {
+        let child = self.nodes[0]
+            .children
+            .iter()
+            .copied()
+            .find(|child| self.nodes[*child].action.as_ref() == Some(action));
+        match child {
+            Some(child) => self.reroot(child),
+            None => {
+                let state = action.apply(&self.nodes[0].state);
+                self.reset(state);
+            }
+        }
+    }
+
+    // Run the configured number of simulations from the root
+    pub fn search(&mut self) {
+        let mut done = 0;
+        while done < self.simulations {
+            if self.nodes[0].expanded && !self.root_noised {
+                self.add_root_noise();
+            }
+            let mut leaves: Vec<(usize, Vec<ChessMove>)> = vec![];
+            while done + leaves.len() < self.simulations && leaves.len() < self.batch_size {
+                let leaf = self.select_leaf();
+                if let Some(value) = self.nodes[leaf].terminal {
+                    self.backup(leaf, value);
+                    done += 1;
+                } else if leaves.iter().any(|(waiting, _)| *waiting == leaf) {
+                    // Everything left leads to a leaf already waiting
+                    self.revert_virtual_visits(leaf);
+                    break;
+                } else if self.repeats_ancestor(leaf) {
+                    // Only a draw while the earlier position is in the
+                    // tree, so it's checked again each time
+                    self.backup(leaf, 0.0);
+                    done += 1;
+                } else {
+                    let (terminal, moves) = self.game_over(leaf);
+                    match terminal {
+                        Some(value) => {
+                            self.nodes[leaf].terminal = Some(value);
+                            self.nodes[leaf].expanded = true;
+                            self.backup(leaf, value);
+                            done += 1;
+                        }
+                        None => leaves.push((leaf, moves)),
+                    }
+                }
+            }
+            if leaves.is_empty() {
+                continue;
+            }
+
+            let states: Vec<GameState> = leaves.iter().map(|(leaf, _)| self.nodes[*leaf].state).collect();
+            let evaluations = self.evaluator.evaluate_batch(&states);
+            for ((leaf, moves), (priors, value)) in leaves.into_iter().zip(evaluations) {
+                self.expand(leaf, &moves, &priors);
+                self.backup(leaf, value);
+                done += 1;
+            }
+        }
+    }
+
+    // How often the search visited each move from the root
+    pub fn visit_counts(&self) -> Vec<(ChessMove, u32)> {
+        self.nodes[0]
+            .children
+            .iter()
+            .map(|child| (self.nodes[*child].action.unwrap(), self.nodes[*child].visits))
+            .collect()
+    }
+
+    // Visit counts as a distribution over policy entries, the target
+    // AlphaZero trains its policy towards
+    pub fn visit_policy(&self, orientation: PolicyOrientation) -> Vec<f32> {
+        let mut policy = vec![0.0; POLICY_SIZE];
+        let counts = self.visit_counts();
+        let total: u32 = counts.iter().map(|(_, visits)| visits).sum();
+        if total == 0 {
+            return policy;
+        }
+        let state = &self.nodes[0].state;
+        for (action, visits) in counts {
+            policy[move_to_policy_index(state, &action, orientation)] = visits as f32 / total as f32;
+        }
+        policy
+    }
+
+    // Choose a move from the root with probability proportional to its
+    // visits raised to 1 / temperature. A temperature of 0 chooses the
+    // most visited move. There's no move if the game is over.
+    pub fn select_move(&mut self, temperature: f64) -> Option<ChessMove> {
+        let counts = self.visit_counts();
+        let most = counts.iter().map(|(_, visits)| *visits).max()?;
+        if temperature <= 0.0 || most == 0 {
+            return counts
+                .into_iter()
+                .find(|(_, visits)| *visits == most)
+                .map(|(action, _)| action);
+        }
+        // Scaled by the most visits, so that low temperatures don't overflow
+        let weights: Vec<f64> = counts
+            .iter()
+            .map(|(_, visits)| (*visits as f64 / most as f64).powf(1.0 / temperature))
+            .collect();
+        let mut target = self.random.unit() * weights.iter().sum::<f64>();
+        for ((action, _), weight) in counts.iter().zip(weights.iter()) {
+            if target < *weight {
+                return Some(*action);
+            }
+            target -= weight;
+        }
+        counts.last().map(|(action, _)| *action)
+    }
+
+    fn reset(&mut self, state: GameState) {
+        self.nodes = vec![Node::new(state, None, None, 1.0)];
+        self.root_noised = false;
+    }
+
+    // Make a node the root, dropping everything not below it
+    fn reroot(&mut self, root: usize) {
+        let mut old_nodes: Vec<Option<Node>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        let mut queue = vec![(root, None)];
+        let mut next = 0;
+        while next < queue.len() {
+            let (old, parent) = queue[next];
+            let mut node = old_nodes[old].take().unwrap();
+            let index = self.nodes.len();
+            for child in node.children.iter() {
+                queue.push((*child, Some(index)));
+            }
+            node.parent = parent;
+            node.children.clear();
+            if let Some(parent) = parent {
+                self.nodes[parent].children.push(index);
+            }
+            self.nodes.push(node);
+            next += 1;
+        }
+        self.nodes[0].action = None;
+        self.root_noised = false;
+    }
+
+    // Descend by PUCT to a node that hasn't been expanded, counting a
+    // virtual visit at each step
+    fn select_leaf(&mut self) -> usize {
+        let mut node = 0;
+        loop {
+            self.nodes[node].virtual_visits += 1;
+            if !self.nodes[node].expanded || self.nodes[node].terminal.is_some() {
+                return node;
+            }
+            node = self.best_child(node);
+        }
+    }
+
+    fn best_child(&self, node: usize) -> usize {
+        let parent = &self.nodes[node];
+        let parent_visits = (parent.visits + parent.virtual_visits) as f32;
+        let mut best = (f32::NEG_INFINITY, parent.children[0]);
+        for child in parent.children.iter() {
+            let child_node = &self.nodes[*child];
+            let visits = (child_node.visits + child_node.virtual_visits) as f32;
+            let value = if visits == 0.0 {
+                0.0
+            } else {
+                (child_node.value_sum - self.virtual_loss * child_node.virtual_visits as f32) / visits
+            };
+            let score = value + self.exploration * child_node.prior * parent_visits.sqrt() / (1.0 + visits);
+            if score > best.0 {
+                best = (score, *child);
+            }
+        }
+        best.1
+    }
+
+    // The value for the side to move if the game is over at a node, and
+    // otherwise its legal moves
+    fn game_over(&self, node: usize) -> (Option<f32>, Vec<ChessMove>) {
+        let state = &self.nodes[node].state;
+        let moves = legal_chess_moves(state);
+        if moves.is_empty() {
+            let value = if color_is_checked(state.to_move, state) { -1.0 } else { 0.0 };
+            return (Some(value), moves);
+        }
+        if insufficient_material(state) {
+            return (Some(0.0), moves);
+        }
+        (None, moves)
+    }
+
+    fn repeats_ancestor(&self, node: usize) -> bool {
+        let key = self.nodes[node].state.hash();
+        let mut ancestor = self.nodes[node].parent;
+        while let Some(earlier) = ancestor {
+            if self.nodes[earlier].state.hash() == key {
+                return true;
+            }
+            ancestor = self.nodes[earlier].parent;
+        }
+        false
+    }
+
+    fn expand(&mut self, node: usize, moves: &[ChessMove], priors: &[f32]) {
+        let state = self.nodes[node].state;
+        let weights: Vec<f32> = moves
+            .iter()
+            .map(|action| {
+                let index = move_to_policy_index(&state, action, PolicyOrientation::SideToMove);
+                priors.get(index).copied().unwrap_or(0.0).max(0.0)
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        for (action, weight) in moves.iter().zip(weights) {
+            let prior = if total > 0.0 { weight / total } else { 1.0 / moves.len() as f32 };
+            let index = self.nodes.len();
+            self.nodes.push(Node::new(action.apply(&state), Some(*action), Some(node), prior));
+            self.nodes[node].children.push(index);
+        }
+        self.nodes[node].expanded = true;
+    }
+
+    // Add a simulation's value, for the side to move at the leaf, to the
+    // leaf and everything above it
+    fn backup(&mut self, leaf: usize, value: f32) {
+        let mut value = -value;
+        let mut node = Some(leaf);
+        while let Some(index) = node {
+            let current = &mut self.nodes[index];
+            current.virtual_visits -= 1;
+            current.visits += 1;
+            current.value_sum += value;
+            value = -value;
+            node = current.parent;
+        }
+    }
+
+    fn revert_virtual_visits(&mut self, leaf: usize) {
+        let mut node = Some(leaf);
+        while let Some(index) = node {
+            self.nodes[index].virtual_visits -= 1;
+            node = self.nodes[index].parent;
+        }
+    }
+
+    fn add_root_noise(&mut self) {
+        self.root_noised = true;
+        let (alpha, weight) = match self.noise {
+            Some(noise) => noise,
+            None => return,
+        };
+        let children = self.nodes[0].children.clone();
+        let samples: Vec<f64> = children.iter().map(|_| gamma(&mut self.random, alpha)).collect();
+        let total: f64 = samples.iter().sum();
+        if total <= 0.0 {
+            return;
+        }
+        for (child, sample) in children.iter().zip(samples) {
+            let prior = self.nodes[*child].prior as f64;
+            self.nodes[*child].prior = ((1.0 - weight) * prior + weight * sample / total) as f32;
+        }
+    }
+}
+
+impl<E: PolicyValueEvaluator> Agent for Mcts<E> {
+    fn select_action(&mut self, state: &GameState) -> ChessMove {
+        self.set_position(state);
+        self.search();
+        let temperature = self.temperature;
+        let action = self.select_move(temperature).expect("no legal move to search");
+        self.advance(&action);
+        action
+    }
+
+    fn name(&self) -> String {
+        format!("mcts {} simulations", self.simulations)
+    }
+}
+
+// A sample from the gamma distribution with this shape and a scale of 1,
+// by Marsaglia and Tsang's method. Normalised, samples for each move make
+// a sample from the Dirichlet distribution.
+fn gamma(random: &mut Random, shape: f64) -> f64 {
+    if shape < 1.0 {
+        return gamma(random, shape + 1.0) * random.unit().powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = normal(random);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = 1.0 - random.unit();
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+// A standard normal sample, by the Box-Muller transform
+fn normal(random: &mut Random) -> f64 {
+    let u = 1.0 - random.unit();
+    let v = random.unit();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
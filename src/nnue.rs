@@ -0,0 +1,402 @@
+// The single responsibility of this module is to evaluate positions with
+// an efficiently updatable neural network.
+//
+// The network has HalfKA inputs: from each player's perspective, one
+// input for every combination of that player's king square and a piece
+// (of either color, kings included) on a square. The inputs feed a
+// hidden layer, the feature transformer, whose values are kept for both
+// perspectives in an accumulator. Because a move only switches a few
+// inputs on or off, the accumulator can be updated instead of recomputed,
+// unless the king of that perspective moves. The clipped hidden values of
+// the side to move, followed by those of the other side, feed a single
+// output.
+//
+// Weights file format, all numbers little-endian:
+//
+//     magic            4 bytes            "CENN"
+//     version          u32                1
+//     hidden size      u32                H
+//     feature biases   H x i16
+//     feature weights  INPUT_SIZE x H x i16, all H weights of input 0
+//                      first, then those of input 1, and so on
+//     output weights   2H x i8, side to move's half first
+//     output bias      i32
+//
+// An input's index is 768 * king + 64 * piece + square. Squares count
+// from a1 = 0 to h8 = 63, and are mirrored vertically for black's
+// perspective so that both players see their own pieces at the bottom.
+// Pieces count pawn, knight, bishop, rook, queen, king for the player
+// whose perspective it is, then the same for their opponent.
+//
+// Hidden values are clipped to 0..=ACTIVATION_LIMIT before the output
+// layer. The output is in units of ACTIVATION_LIMIT * OUTPUT_WEIGHT_SCALE
+// per EVALUATION_SCALE centipawns, so a float network is quantized by
+// multiplying feature weights and biases by ACTIVATION_LIMIT, output
+// weights by OUTPUT_WEIGHT_SCALE, and the output bias by both.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    Piece,
+    PieceName::{Pawn, Knight, Bishop, Rook, Queen, King},
+    Color,
+    Color::{White, Black},
+};
+
+use crate::evaluation::{
+    Evaluator,
+    EvaluationTracker,
+};
+
+use crate::hashing::next_random;
+
+use std::path::Path;
+use std::sync::Arc;
+
+pub const INPUT_SIZE: usize = 64 * 12 * 64;
+pub const ACTIVATION_LIMIT: i32 = 127;
+pub const OUTPUT_WEIGHT_SCALE: i32 = 64;
+pub const EVALUATION_SCALE: i32 = 400;
+
+// Evaluations are clamped to this many centipawns either way, well clear
+// of mate scores
+const MAX_EVALUATION: i64 = 30_000;
+
+const MAGIC: &[u8; 4] = b"CENN";
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    // How many bytes or values were expected, and how many were found
+    WrongLength(usize, usize),
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Network {
+    hidden_size: usize,
+    feature_biases: Vec<i16>,
+    feature_weights: Vec<i16>,
+    output_weights: Vec<i8>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn new(
+        hidden_size: usize,
+        feature_biases: Vec<i16>,
+        feature_weights: Vec<i16>,
+        output_weights: Vec<i8>,
+        output_bias: i32,
+    ) -> Result<Network, NetworkError> {
+        if feature_biases.len() != hidden_size {
+            return Err(NetworkError::WrongLength(hidden_size, feature_biases.len()));
+        }
+        if feature_weights.len() != INPUT_SIZE * hidden_size {
+            return Err(NetworkError::WrongLength(INPUT_SIZE * hidden_size, feature_weights.len()));
+        }
+        if output_weights.len() != 2 * hidden_size {
+            return Err(NetworkError::WrongLength(2 * hidden_size, output_weights.len()));
+        }
+        Ok(Network { hidden_size, feature_biases, feature_weights, output_weights, output_bias })
+    }
+
+    // A network with small random weights, as a starting point for training
+    // or for testing. The same seed always gives the same network.
+    pub fn random(hidden_size: usize, seed: u64) -> Network {
+        let mut seed = seed;
+        let mut next = |range: i64| {
+            let random;
+            (seed, random) = next_random(seed);
+            (random % (2 * range as u64 + 1)) as i64 - range
+        };
+        let feature_biases = (0..hidden_size).map(|_| next(32) as i16).collect();
+        let feature_weights = (0..INPUT_SIZE * hidden_size).map(|_| next(16) as i16).collect();
+        let output_weights = (0..2 * hidden_size).map(|_| next(64) as i8).collect();
+        let output_bias = next(1000) as i32;
+        Network { hidden_size, feature_biases, feature_weights, output_weights, output_bias }
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NetworkError> {
+        if bytes.len() < 12 {
+            return Err(NetworkError::WrongLength(12, bytes.len()));
+        }
+        if &bytes[0..4] != MAGIC {
+            return Err(NetworkError::BadMagic);
+        }
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version != VERSION {
+            return Err(NetworkError::UnsupportedVersion(version));
+        }
+        let hidden_size = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+
+        let expected = 12 + 2 * hidden_size + 2 * INPUT_SIZE * hidden_size + 2 * hidden_size + 4;
+        if bytes.len() != expected {
+            return Err(NetworkError::WrongLength(expected, bytes.len()));
+        }
+
+        let read_i16s = |start: usize, count: usize| -> Vec<i16> {
+            bytes[start..start + 2 * count]
+                .chunks(2)
+                .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                .collect()
+        };
+        let weights_start = 12 + 2 * hidden_size;
+        let output_start = weights_start + 2 * INPUT_SIZE * hidden_size;
+        let bias_start = output_start + 2 * hidden_size;
+
+        let feature_biases = read_i16s(12, hidden_size);
+        let feature_weights = read_i16s(weights_start, INPUT_SIZE * hidden_size);
+        let output_weights = bytes[output_start..bias_start]
+            .iter()
+            .map(|byte| *byte as i8)
+            .collect();
+        let output_bias = i32::from_le_bytes([
+            bytes[bias_start],
+            bytes[bias_start + 1],
+            bytes[bias_start + 2],
+            bytes[bias_start + 3],
+        ]);
+
+        Network::new(hidden_size, feature_biases, feature_weights, output_weights, output_bias)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden_size as u32).to_le_bytes());
+        for value in self.feature_biases.iter().chain(self.feature_weights.iter()) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend(self.output_weights.iter().map(|weight| *weight as u8));
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Network, NetworkError> {
+        let bytes = std::fs::read(path).map_err(NetworkError::Io)?;
+        Network::from_bytes(&bytes)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    fn weights_of(&self, feature: usize) -> &[i16] {
+        let start = feature * self.hidden_size;
+        &self.feature_weights[start..start + self.hidden_size]
+    }
+
+    // The hidden layer of one perspective, computed from scratch
+    fn refresh(&self, state: &GameState, perspective: Color, hidden: &mut [i16]) {
+        hidden.copy_from_slice(&self.feature_biases);
+        let king = match king_square(state, perspective) {
+            Some(king) => king,
+            None => return,
+        };
+        for (square, maybe_piece) in state.squares.iter().enumerate() {
+            if let Some(piece) = maybe_piece {
+                let feature = feature_index(perspective, king, piece, square);
+                add_weights(hidden, self.weights_of(feature));
+            }
+        }
+    }
+
+    fn output(&self, accumulator: &Accumulator, to_move: Color) -> i32 {
+        let (ours, theirs) = match to_move {
+            White => (&accumulator.white, &accumulator.black),
+            Black => (&accumulator.black, &accumulator.white),
+        };
+        // Wide enough for any hidden size and weights a file can hold
+        let mut sum = self.output_bias as i64;
+        let halves = ours.iter().chain(theirs.iter());
+        for (value, weight) in halves.zip(self.output_weights.iter()) {
+            let activation = (*value as i64).clamp(0, ACTIVATION_LIMIT as i64);
+            sum += activation * *weight as i64;
+        }
+        let evaluation = sum * EVALUATION_SCALE as i64 / (ACTIVATION_LIMIT * OUTPUT_WEIGHT_SCALE) as i64;
+        evaluation.clamp(-MAX_EVALUATION, MAX_EVALUATION) as i32
+    }
+
+    fn accumulator(&self, state: &GameState) -> Accumulator {
+        let mut accumulator = Accumulator {
+            white: vec![0; self.hidden_size],
+            black: vec![0; self.hidden_size],
+        };
+        self.refresh(state, White, &mut accumulator.white);
+        self.refresh(state, Black, &mut accumulator.black);
+        accumulator
+    }
+}
+
+// The index of the input that is on when `piece` stands on `square` and
+// the king of `perspective` stands on `king`
+pub fn feature_index(perspective: Color, king: usize, piece: &Piece, square: usize) -> usize {
+    let orient = |square: usize| match perspective {
+        White => square,
+        Black => square ^ 56,
+    };
+    let kind = match piece.name {
+        Pawn => 0,
+        Knight => 1,
+        Bishop => 2,
+        Rook => 3,
+        Queen => 4,
+        King => 5,
+    };
+    let piece_index = if piece.color == perspective { kind } else { kind + 6 };
+    768 * orient(king) + 64 * piece_index + orient(square)
+}
+
+fn king_square(state: &GameState, color: Color) -> Option<usize> {
+    state.squares.iter().position(|maybe_piece| match maybe_piece {
+        Some(piece) => piece.color == color && piece.name == King,
+        None => false,
+    })
+}
+
+// Feature transformer sums wrap rather than overflow, so that updating an
+// accumulator always gives the same result as recomputing it. Networks
+// should be trained so that they stay within range anyway.
+fn add_weights(hidden: &mut [i16], weights: &[i16]) {
+    for (value, weight) in hidden.iter_mut().zip(weights.iter()) {
+        *value = value.wrapping_add(*weight);
+    }
+}
+
+fn subtract_weights(hidden: &mut [i16], weights: &[i16]) {
+    for (value, weight) in hidden.iter_mut().zip(weights.iter()) {
+        *value = value.wrapping_sub(*weight);
+    }
+}
+
+fn same_piece(a: &Option<Piece>, b: &Option<Piece>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.color == b.color && a.name == b.name,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// The hidden layer from both perspectives
+#[derive(Debug)]
+#[derive(Clone)]
+struct Accumulator {
+    white: Vec<i16>,
+    black: Vec<i16>,
+}
+
+// Evaluates with a network. Called directly, every evaluation starts from
+// scratch; searches use its tracker, which updates accumulators instead.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct NnueEvaluator {
+    network: Arc<Network>,
+}
+
+impl NnueEvaluator {
+    pub fn new(network: Network) -> NnueEvaluator {
+        NnueEvaluator { network: Arc::new(network) }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<NnueEvaluator, NetworkError> {
+        Network::load(path).map(NnueEvaluator::new)
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn evaluate(&self, state: &GameState) -> i32 {
+        self.network.output(&self.network.accumulator(state), state.to_move)
+    }
+
+    fn tracker(&self) -> Option<Box<dyn EvaluationTracker>> {
+        Some(Box::new(AccumulatorStack::new(Arc::clone(&self.network))))
+    }
+}
+
+// One accumulator for every position in the line being searched
+pub struct AccumulatorStack {
+    network: Arc<Network>,
+    stack: Vec<Accumulator>,
+}
+
+impl AccumulatorStack {
+    pub fn new(network: Arc<Network>) -> AccumulatorStack {
+        AccumulatorStack { network, stack: vec![] }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+}
+
+impl EvaluationTracker for AccumulatorStack {
+    fn reset(&mut self, state: &GameState) {
+        self.stack.clear();
+        self.stack.push(self.network.accumulator(state));
+    }
+
+    fn make(&mut self, before: &GameState, after: &GameState) {
+        let mut accumulator = match self.stack.last() {
+            Some(top) => top.clone(),
+            None => self.network.accumulator(before),
+        };
+
+        for perspective in [White, Black].iter() {
+            let hidden = match perspective {
+                White => &mut accumulator.white,
+                Black => &mut accumulator.black,
+            };
+            let king = king_square(after, *perspective);
+
+            // Every input depends on the king square, so a king move means
+            // starting over
+            if king.is_none() || king != king_square(before, *perspective) {
+                self.network.refresh(after, *perspective, hidden);
+                continue;
+            }
+            let king = king.unwrap();
+
+            for square in 0..64 {
+                let (old, new) = (&before.squares[square], &after.squares[square]);
+                if same_piece(old, new) {
+                    continue;
+                }
+                if let Some(piece) = old {
+                    let feature = feature_index(*perspective, king, piece, square);
+                    subtract_weights(hidden, self.network.weights_of(feature));
+                }
+                if let Some(piece) = new {
+                    let feature = feature_index(*perspective, king, piece, square);
+                    add_weights(hidden, self.network.weights_of(feature));
+                }
+            }
+        }
+
+        self.stack.push(accumulator);
+    }
+
+    fn unmake(&mut self) {
+        self.stack.pop();
+    }
+
+    fn evaluate(&self, state: &GameState) -> i32 {
+        match self.stack.last() {
+            Some(top) => self.network.output(top, state.to_move),
+            None => self.network.output(&self.network.accumulator(state), state.to_move),
+        }
+    }
+}
@@ -8,6 +8,14 @@ use crate::pieces::{
     Color::{White,Black},
 };
 
+use crate::actions::{
+    ChessMove,
+    CastleDirection,
+    CastleDirection::{Kingside, Queenside},
+};
+
+use crate::utilities::legal_chess_moves;
+
 // Get algebraic notation from index
 pub fn square_index_to_algebraic(square: usize) -> String {
     let rank = (square as u8 % 8 + 97) as char;
@@ -120,3 +128,127 @@ pub fn fen_notation(state: &GameState) -> String {
 }
 
 
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum UciMoveError {
+    // Not of the form e2e4 or e7e8q
+    InvalidSyntax(String),
+    // Well formed, but not a legal move in the position
+    IllegalMove(String),
+}
+
+// The squares a move takes a piece from and to. For castling that is the
+// king, and for en passant the capturing pawn. Castle and EnPassant don't
+// record which side or square they belong to, so the state before the
+// move is needed.
+pub fn move_squares(state: &GameState, action: &ChessMove) -> (usize, usize) {
+    match action {
+        ChessMove::Move(action) => (action.from, action.to),
+        ChessMove::Capture(action) => (action.with, action.on),
+        ChessMove::EnPassant(action) => (action.with, state.en_passant_square.unwrap_or(0)),
+        ChessMove::Promotion(action) => (action.moving_from, action.to),
+        ChessMove::Castle(action) => {
+            let rank = match state.to_move {
+                White => 0,
+                Black => 56,
+            };
+            match action.direction {
+                Kingside => (rank + 4, rank + 6),
+                Queenside => (rank + 4, rank + 2),
+            }
+        },
+    }
+}
+
+// Long algebraic notation as used by UCI and CECP: the origin and
+// destination squares as given by move_squares, followed by the piece
+// promoted to, if any
+pub fn to_uci(state: &GameState, action: &ChessMove) -> String {
+    let (from, to) = move_squares(state, action);
+    let suffix = match action {
+        ChessMove::Promotion(action) => match action.pawn_becomes {
+            PieceName::Queen => "q",
+            PieceName::Rook => "r",
+            PieceName::Bishop => "b",
+            PieceName::Knight => "n",
+            _ => "",
+        },
+        _ => "",
+    };
+    format!("{}{}{}", square_index_to_algebraic(from), square_index_to_algebraic(to), suffix)
+}
+
+// Find the legal move written in long algebraic notation
+pub fn parse_uci_move(state: &GameState, text: &str) -> Result<ChessMove, UciMoveError> {
+    let well_formed = text.is_ascii()
+        && (text.len() == 4 || text.len() == 5)
+        && square_algebraic_to_index(&text[0..2]).is_some()
+        && square_algebraic_to_index(&text[2..4]).is_some()
+        && (text.len() == 4 || "qrbn".contains(&text[4..]));
+    if !well_formed {
+        return Err(UciMoveError::InvalidSyntax(text.to_string()));
+    }
+
+    legal_chess_moves(state)
+        .into_iter()
+        .find(|action| to_uci(state, action) == text)
+        .ok_or_else(|| UciMoveError::IllegalMove(text.to_string()))
+}
+
+// Find the legal move written in standard algebraic notation, allowing
+// for the usual variations like "0-0", "e8Q" and missing check marks
+pub fn parse_san(state: &GameState, text: &str) -> Option<ChessMove> {
+    let text: String = text.chars().filter(|c| !"+#!?=x".contains(*c)).collect();
+    let moves = legal_chess_moves(state);
+
+    match text.as_str() {
+        "O-O" | "0-0" => return moves.into_iter().find(|action| is_castle(action, Kingside)),
+        "O-O-O" | "0-0-0" => return moves.into_iter().find(|action| is_castle(action, Queenside)),
+        _ => (),
+    }
+
+    let mut chars: Vec<char> = text.chars().collect();
+    let piece = match chars.first() {
+        Some('K') => PieceName::King,
+        Some('Q') => PieceName::Queen,
+        Some('R') => PieceName::Rook,
+        Some('B') => PieceName::Bishop,
+        Some('N') => PieceName::Knight,
+        _ => PieceName::Pawn,
+    };
+    if piece != PieceName::Pawn {
+        chars.remove(0);
+    }
+    let promotion = match chars.last() {
+        Some(c) if "QRBNqrbn".contains(*c) && piece == PieceName::Pawn => {
+            let c = c.to_ascii_lowercase();
+            chars.pop();
+            Some(c)
+        },
+        _ => None,
+    };
+    if chars.len() < 2 {
+        return None;
+    }
+    let destination: String = chars[chars.len() - 2..].iter().collect();
+    let hints: Vec<char> = chars[..chars.len() - 2].to_vec();
+
+    let mut candidates = moves.into_iter().filter(|action| {
+        let uci = to_uci(state, action);
+        let origin = square_algebraic_to_index(&uci[0..2]).unwrap();
+        let moving = state.squares[origin].map(|piece| piece.name);
+        moving == Some(piece)
+            && uci[2..4] == destination
+            && uci[4..].chars().next() == promotion
+            && hints.iter().all(|hint| uci[0..2].contains(*hint))
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(action), None) => Some(action),
+        _ => None,
+    }
+}
+
+fn is_castle(action: &ChessMove, direction: CastleDirection) -> bool {
+    matches!(action, ChessMove::Castle(castle) if castle.direction == direction)
+}
@@ -37,6 +37,55 @@ impl Piece {
     pub fn new(color: Color, name: PieceName) -> Piece {
         Piece { color, name }
     }
+
+    // The letter used for this piece in FEN and board diagrams,
+    // uppercase for white and lowercase for black
+    pub fn as_char(&self) -> char {
+        let c = match self.name {
+            PieceName::Pawn => 'p',
+            PieceName::Bishop => 'b',
+            PieceName::Knight => 'n',
+            PieceName::Rook => 'r',
+            PieceName::Queen => 'q',
+            PieceName::King => 'k',
+        };
+        match self.color {
+            Color::White => c.to_ascii_uppercase(),
+            Color::Black => c,
+        }
+    }
+
+    // The inverse of as_char
+    pub fn from_char(c: char) -> Option<Piece> {
+        let name = match c.to_ascii_lowercase() {
+            'p' => PieceName::Pawn,
+            'b' => PieceName::Bishop,
+            'n' => PieceName::Knight,
+            'r' => PieceName::Rook,
+            'q' => PieceName::Queen,
+            'k' => PieceName::King,
+            _ => return None,
+        };
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        Some(Piece::new(color, name))
+    }
+
+    pub fn as_unicode(&self) -> char {
+        match (self.color, self.name) {
+            (Color::White, PieceName::Pawn) => '♙',
+            (Color::White, PieceName::Bishop) => '♗',
+            (Color::White, PieceName::Knight) => '♘',
+            (Color::White, PieceName::Rook) => '♖',
+            (Color::White, PieceName::Queen) => '♕',
+            (Color::White, PieceName::King) => '♔',
+            (Color::Black, PieceName::Pawn) => '♟',
+            (Color::Black, PieceName::Bishop) => '♝',
+            (Color::Black, PieceName::Knight) => '♞',
+            (Color::Black, PieceName::Rook) => '♜',
+            (Color::Black, PieceName::Queen) => '♛',
+            (Color::Black, PieceName::King) => '♚',
+        }
+    }
 }
 
 impl ToString for Piece {
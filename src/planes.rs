@@ -0,0 +1,264 @@
+// The single responsibility of this module is to turn positions into the
+// stacks of 8x8 planes that neural networks take as input.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    Color,
+    PieceName,
+    Color::{White, Black},
+};
+
+use crate::actions::{
+    Action,
+    ChessMove,
+};
+
+use crate::arena::is_irreversible;
+
+use crate::policy::PolicyOrientation;
+
+use std::collections::VecDeque;
+
+// Planes that don't depend on history: side to move, four castling rights,
+// the en passant square, the halfmove clock and the move number
+const STATE_PLANES: usize = 8;
+
+const PIECE_ORDER: [PieceName; 6] = [
+    PieceName::Pawn,
+    PieceName::Knight,
+    PieceName::Bishop,
+    PieceName::Rook,
+    PieceName::Queen,
+    PieceName::King,
+];
+
+// A type planes can be written in. Flags are 0 or 1, and the clock planes
+// hold plain counts, which for u8 stop at 255.
+pub trait PlaneValue: Copy {
+    fn from_count(count: usize) -> Self;
+}
+
+impl PlaneValue for f32 {
+    fn from_count(count: usize) -> f32 {
+        count as f32
+    }
+}
+
+impl PlaneValue for u8 {
+    fn from_count(count: usize) -> u8 {
+        count.min(255) as u8
+    }
+}
+
+// Encodes positions as planes of 64 values, square a1 first, in this
+// order:
+//
+// - 12 piece planes for the current position and for each earlier one
+//   kept in the history: pawns, knights, bishops, rooks, queens and king
+//   of one side, then the other. The first side is white, or the side to
+//   move if the board is flipped.
+// - the side to move, all ones for black
+// - castling rights: kingside then queenside for the first side, then
+//   the same for the second
+// - the en passant square, if any
+// - the halfmove clock and the move number, repeated on every square
+//
+// When the board is flipped every plane is flipped with it, history
+// included.
+pub struct PlaneEncoder {
+    history: usize,
+    orientation: PolicyOrientation,
+}
+
+impl Default for PlaneEncoder {
+    fn default() -> PlaneEncoder {
+        PlaneEncoder::new()
+    }
+}
+
+impl PlaneEncoder {
+    // No history, from the side to move's point of view
+    pub fn new() -> PlaneEncoder {
+        PlaneEncoder { history: 1, orientation: PolicyOrientation::SideToMove }
+    }
+
+    // Include piece planes for this many positions, counting the current
+    // one. Earlier positions that didn't happen are left as zeros.
+    pub fn with_history(mut self, positions: usize) -> PlaneEncoder {
+        self.history = positions.max(1);
+        self
+    }
+
+    pub fn with_orientation(mut self, orientation: PolicyOrientation) -> PlaneEncoder {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn plane_count(&self) -> usize {
+        12 * self.history + STATE_PLANES
+    }
+
+    // How long buffers must be
+    pub fn size(&self) -> usize {
+        64 * self.plane_count()
+    }
+
+    // Encode a lone position, with clocks at their starting values.
+    // Panics if `buffer` isn't `size()` long.
+    pub fn encode_state<T: PlaneValue>(&self, state: &GameState, buffer: &mut [T]) {
+        self.encode(&[*state], 0, 1, buffer);
+    }
+
+    // Encode the current position of a history, with the positions and
+    // clocks it keeps
+    pub fn encode_history<T: PlaneValue>(&self, history: &PositionHistory, buffer: &mut [T]) {
+        self.write(
+            history.positions.iter().rev(),
+            history.halfmove_clock,
+            history.fullmove_number,
+            buffer,
+        );
+    }
+
+    // Encode the last of `positions`, oldest first, with those before it
+    // as history. Panics if `buffer` isn't `size()` long.
+    pub fn encode<T: PlaneValue>(
+        &self,
+        positions: &[GameState],
+        halfmove_clock: usize,
+        fullmove_number: usize,
+        buffer: &mut [T],
+    ) {
+        self.write(positions.iter().rev(), halfmove_clock, fullmove_number, buffer);
+    }
+
+    // Positions come newest first
+    fn write<'a, T: PlaneValue, I: Iterator<Item = &'a GameState>>(
+        &self,
+        mut positions: I,
+        halfmove_clock: usize,
+        fullmove_number: usize,
+        buffer: &mut [T],
+    ) {
+        assert_eq!(self.size(), buffer.len(), "plane buffer has the wrong length");
+        let zero = T::from_count(0);
+        let one = T::from_count(1);
+        for value in buffer.iter_mut() {
+            *value = zero;
+        }
+
+        let state = positions.next().expect("no position to encode");
+        let flip = self.orientation == PolicyOrientation::SideToMove && state.to_move == Black;
+        let first = if flip { Black } else { White };
+        let orient = |square: usize| if flip { square ^ 56 } else { square };
+
+        let steps = std::iter::once(state).chain(positions).take(self.history);
+        for (step, earlier) in steps.enumerate() {
+            for (square, maybe_piece) in earlier.squares.iter().enumerate() {
+                if let Some(piece) = maybe_piece {
+                    let side = if piece.color == first { 0 } else { 6 };
+                    let kind = PIECE_ORDER.iter().position(|name| *name == piece.name).unwrap();
+                    let plane = 12 * step + side + kind;
+                    buffer[64 * plane + orient(square)] = one;
+                }
+            }
+        }
+
+        let base = 12 * self.history;
+        let mut fill = |plane: usize, value: T| {
+            for square in 0..64 {
+                buffer[64 * (base + plane) + square] = value;
+            }
+        };
+        if state.to_move == Black {
+            fill(0, one);
+        }
+        let second = opposite(first);
+        let rights = [
+            castling_rights(state, first).0,
+            castling_rights(state, first).1,
+            castling_rights(state, second).0,
+            castling_rights(state, second).1,
+        ];
+        for (i, right) in rights.iter().enumerate() {
+            if *right {
+                fill(1 + i, one);
+            }
+        }
+        fill(6, T::from_count(halfmove_clock));
+        fill(7, T::from_count(fullmove_number));
+        if let Some(square) = state.en_passant_square {
+            buffer[64 * (base + 5) + orient(square)] = one;
+        }
+    }
+}
+
+// The last few positions of a game and its clocks, kept up to date by
+// whoever plays or replays the game, so that each position can be encoded
+// without going back over the moves before it
+pub struct PositionHistory {
+    positions: VecDeque<GameState>,
+    length: usize,
+    halfmove_clock: usize,
+    fullmove_number: usize,
+}
+
+impl PositionHistory {
+    // Keep this many positions, counting the current one, which should be
+    // at least as many as the encoder's history. Clocks start at 0 and 1.
+    pub fn new(start: GameState, length: usize) -> PositionHistory {
+        let length = length.max(1);
+        let mut positions = VecDeque::with_capacity(length + 1);
+        positions.push_back(start);
+        PositionHistory { positions, length, halfmove_clock: 0, fullmove_number: 1 }
+    }
+
+    // For games that don't start with fresh clocks, as a FEN can say
+    pub fn with_clocks(mut self, halfmove_clock: usize, fullmove_number: usize) -> PositionHistory {
+        self.halfmove_clock = halfmove_clock;
+        self.fullmove_number = fullmove_number;
+        self
+    }
+
+    pub fn current(&self) -> &GameState {
+        self.positions.back().unwrap()
+    }
+
+    pub fn halfmove_clock(&self) -> usize {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> usize {
+        self.fullmove_number
+    }
+
+    // Play a move from the current position, forgetting the oldest
+    // position if there are too many
+    pub fn push(&mut self, action: &ChessMove) {
+        let state = *self.current();
+        self.halfmove_clock = if is_irreversible(&state, action) { 0 } else { self.halfmove_clock + 1 };
+        if state.to_move == Black {
+            self.fullmove_number += 1;
+        }
+        self.positions.push_back(action.apply(&state));
+        if self.positions.len() > self.length {
+            self.positions.pop_front();
+        }
+    }
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        White => Black,
+        Black => White,
+    }
+}
+
+// Kingside and queenside
+fn castling_rights(state: &GameState, color: Color) -> (bool, bool) {
+    match color {
+        White => (state.white_can_castle_kingside, state.white_can_castle_queenside),
+        Black => (state.black_can_castle_kingside, state.black_can_castle_queenside),
+    }
+}
@@ -0,0 +1,93 @@
+// The single responsibility of this module is to number moves the way
+// AlphaZero's policy head does, so that a network's flat output vector
+// can be read as a distribution over moves.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    PieceName,
+    Color::Black,
+};
+
+use crate::actions::ChessMove;
+
+use crate::utilities::legal_chess_moves;
+
+use crate::notation::move_squares;
+
+// 73 move types for each of the 64 origin squares
+pub const POLICY_SIZE: usize = 64 * 73;
+
+// Slides in each of these directions, from 1 to 7 squares, are planes 0
+// to 55: direction * 7 + distance - 1. Queen promotions are slides too.
+const DIRECTIONS: [(i32, i32); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+
+// Knight jumps are planes 56 to 63
+const KNIGHT_JUMPS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+
+// Promotions to a knight, bishop or rook, capturing towards the a file,
+// straight ahead or capturing towards the h file, are planes 64 to 72:
+// 64 + piece * 3 + file change + 1
+const UNDERPROMOTIONS: [PieceName; 3] = [PieceName::Knight, PieceName::Bishop, PieceName::Rook];
+
+// Whose point of view squares are numbered from
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum PolicyOrientation {
+    // a1 is square 0 for both sides
+    White,
+    // The board is flipped for black, so that the side to move always
+    // plays up the board and the same move pattern has the same number
+    SideToMove,
+}
+
+// The policy vector entry of a move: origin square * 73 + move type
+pub fn move_to_policy_index(state: &GameState, action: &ChessMove, orientation: PolicyOrientation) -> usize {
+    let (from, to) = move_squares(state, action);
+    let (from, to) = match (orientation, state.to_move) {
+        (PolicyOrientation::SideToMove, Black) => (from ^ 56, to ^ 56),
+        _ => (from, to),
+    };
+    let dx = (to % 8) as i32 - (from % 8) as i32;
+    let dy = (to / 8) as i32 - (from / 8) as i32;
+
+    let underpromotion = match action {
+        ChessMove::Promotion(promotion) => UNDERPROMOTIONS
+            .iter()
+            .position(|piece| *piece == promotion.pawn_becomes),
+        _ => None,
+    };
+    let plane = if let Some(piece) = underpromotion {
+        64 + piece as i32 * 3 + dx + 1
+    } else if let Some(jump) = KNIGHT_JUMPS.iter().position(|jump| *jump == (dx, dy)) {
+        56 + jump as i32
+    } else {
+        let direction = DIRECTIONS
+            .iter()
+            .position(|direction| *direction == (dx.signum(), dy.signum()))
+            .unwrap() as i32;
+        direction * 7 + dx.abs().max(dy.abs()) - 1
+    };
+    from * 73 + plane as usize
+}
+
+// The legal move with this policy entry, if there is one
+pub fn policy_index_to_move(state: &GameState, index: usize, orientation: PolicyOrientation) -> Option<ChessMove> {
+    if index >= POLICY_SIZE {
+        return None;
+    }
+    legal_chess_moves(state)
+        .into_iter()
+        .find(|action| move_to_policy_index(state, action, orientation) == index)
+}
+
+// Which policy entries are legal moves
+pub fn policy_legal_mask(state: &GameState, orientation: PolicyOrientation) -> Vec<bool> {
+    let mut mask = vec![false; POLICY_SIZE];
+    for action in legal_chess_moves(state) {
+        mask[move_to_policy_index(state, &action, orientation)] = true;
+    }
+    mask
+}
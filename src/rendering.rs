@@ -0,0 +1,186 @@
+// The single responsibility of this module is to draw GameStates as
+// text for people reading them in a terminal.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    PieceName::King,
+    Color,
+    Color::{White, Black},
+};
+
+use crate::utilities::color_is_checked;
+
+const RESET: &str = "\x1b[0m";
+const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const LAST_MOVE_SQUARE: &str = "\x1b[48;5;143m";
+const CHECKED_SQUARE: &str = "\x1b[48;5;167m";
+const WHITE_PIECE: &str = "\x1b[1;97m";
+const BLACK_PIECE: &str = "\x1b[1;30m";
+
+// Renders a board as text. Highlights are drawn with square
+// backgrounds, so they only appear when ANSI colors are enabled.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct TextRenderer {
+    unicode: bool,
+    coordinates: bool,
+    perspective: Color,
+    ansi_colors: bool,
+    last_move: Option<(usize, usize)>,
+    highlight_check: bool,
+}
+
+impl TextRenderer {
+    // Plain ASCII letters from white's side, matching GameState::to_string
+    pub fn new() -> TextRenderer {
+        TextRenderer {
+            unicode: false,
+            coordinates: false,
+            perspective: White,
+            ansi_colors: false,
+            last_move: None,
+            highlight_check: false,
+        }
+    }
+
+    pub fn unicode(mut self, enabled: bool) -> TextRenderer {
+        self.unicode = enabled;
+        self
+    }
+
+    pub fn coordinates(mut self, enabled: bool) -> TextRenderer {
+        self.coordinates = enabled;
+        self
+    }
+
+    // The color whose pieces are drawn at the bottom of the board
+    pub fn perspective(mut self, color: Color) -> TextRenderer {
+        self.perspective = color;
+        self
+    }
+
+    pub fn ansi_colors(mut self, enabled: bool) -> TextRenderer {
+        self.ansi_colors = enabled;
+        self
+    }
+
+    pub fn last_move(mut self, from: usize, to: usize) -> TextRenderer {
+        self.last_move = Some((from, to));
+        self
+    }
+
+    pub fn highlight_check(mut self, enabled: bool) -> TextRenderer {
+        self.highlight_check = enabled;
+        self
+    }
+
+    pub fn render(&self, state: &GameState) -> String {
+        let mut output = String::new();
+        let checked_king = if self.highlight_check {
+            checked_king_square(state)
+        } else {
+            None
+        };
+
+        for row in 0..8 {
+            let rank = if self.perspective == White { 7 - row } else { row };
+            if self.coordinates {
+                output.push_str(&format!("{} ", rank + 1));
+            }
+            for column in 0..8 {
+                let file = if self.perspective == White { column } else { 7 - column };
+                let square = 8 * rank + file;
+                self.render_square(state, square, checked_king, &mut output);
+            }
+            output.push('\n');
+        }
+
+        if self.coordinates {
+            output.push_str("  ");
+            for column in 0..8 {
+                let file = if self.perspective == White { column } else { 7 - column };
+                let label = (b'a' + file as u8) as char;
+                if self.ansi_colors {
+                    output.push(' ');
+                    output.push(label);
+                    output.push(' ');
+                } else {
+                    output.push(label);
+                    output.push(' ');
+                }
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+
+    fn render_square(
+        &self,
+        state: &GameState,
+        square: usize,
+        checked_king: Option<usize>,
+        output: &mut String,
+    ) {
+        let symbol = match state.squares[square] {
+            None if self.unicode => '·',
+            None => '.',
+            Some(piece) if self.unicode => piece.as_unicode(),
+            Some(piece) => piece.as_char(),
+        };
+
+        if !self.ansi_colors {
+            output.push(symbol);
+            output.push(' ');
+            return;
+        }
+
+        let is_last_move = match self.last_move {
+            Some((from, to)) => square == from || square == to,
+            None => false,
+        };
+        let is_light = (square / 8 + square % 8) % 2 == 1;
+
+        let background = if checked_king == Some(square) {
+            CHECKED_SQUARE
+        } else if is_last_move {
+            LAST_MOVE_SQUARE
+        } else if is_light {
+            LIGHT_SQUARE
+        } else {
+            DARK_SQUARE
+        };
+
+        let foreground = match state.squares[square] {
+            Some(piece) if piece.color == Black => BLACK_PIECE,
+            _ => WHITE_PIECE,
+        };
+
+        let symbol = if state.squares[square].is_none() { ' ' } else { symbol };
+        output.push_str(background);
+        output.push_str(foreground);
+        output.push(' ');
+        output.push(symbol);
+        output.push(' ');
+        output.push_str(RESET);
+    }
+}
+
+impl Default for TextRenderer {
+    fn default() -> TextRenderer {
+        TextRenderer::new()
+    }
+}
+
+// Find the king of the player to move, if that king is in check
+fn checked_king_square(state: &GameState) -> Option<usize> {
+    if !color_is_checked(state.to_move, state) {
+        return None;
+    }
+    state.squares.iter().position(|maybe_piece| match maybe_piece {
+        Some(piece) => piece.name == King && piece.color == state.to_move,
+        None => false,
+    })
+}
@@ -0,0 +1,1146 @@
+// The single responsibility of this module is to choose actions by
+// searching the tree of game states that can follow a position.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    PieceName::{Pawn, King},
+    Color,
+    Color::{White, Black},
+};
+
+use crate::actions::{
+    Action,
+    ChessMove,
+    CastleDirection::{Kingside, Queenside},
+};
+
+use crate::hashing::piece_index;
+
+use crate::utilities::{
+    color_is_checked,
+    legal_chess_moves,
+    legal_captures,
+    legal_en_passants,
+    legal_promotions,
+    piece_value,
+};
+
+use crate::evaluation::{
+    Evaluator,
+    EvaluationTracker,
+    ClassicalEvaluator,
+};
+
+use crate::exchange::see;
+
+use crate::transposition::{
+    TranspositionTable,
+    Bound,
+    score_to_table,
+    score_from_table,
+};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// The score of delivering checkmate immediately. Mates further away score
+// one point less per ply, so shorter mates are always preferred.
+pub const MATE_SCORE: i32 = 100_000;
+
+// Any score at least this far from zero is a forced mate
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - 1_000;
+
+const INFINITY: i32 = MATE_SCORE + 1;
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SearchResult {
+    pub best_move: Option<ChessMove>,
+    // Centipawns from the perspective of the player to move
+    pub score: i32,
+    pub depth: usize,
+    pub nodes: u64,
+    // The expected line of play in algebraic notation, starting with best_move
+    pub principal_variation: Vec<String>,
+    // The same line as moves
+    pub principal_variation_moves: Vec<ChessMove>,
+    // The best lines found, best first. Holds just the principal variation
+    // unless the search was asked for more.
+    pub lines: Vec<SearchLine>,
+}
+
+impl SearchResult {
+    // The number of moves until mate, negative when the player to move is
+    // the one being mated. None if the score isn't a forced mate.
+    pub fn mate_in(&self) -> Option<i32> {
+        mate_distance(self.score)
+    }
+}
+
+// One of the lines found by a MultiPV search, each starting with a
+// different move
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SearchLine {
+    pub first_move: ChessMove,
+    // Centipawns from the perspective of the player to move
+    pub score: i32,
+    pub depth: usize,
+    pub principal_variation: Vec<String>,
+    pub principal_variation_moves: Vec<ChessMove>,
+}
+
+impl SearchLine {
+    pub fn mate_in(&self) -> Option<i32> {
+        mate_distance(self.score)
+    }
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_THRESHOLD
+}
+
+pub fn mate_distance(score: i32) -> Option<i32> {
+    if !is_mate_score(score) {
+        return None;
+    }
+    let plies = MATE_SCORE - score.abs();
+    let moves = (plies + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+// Search every line of play `depth` plies deep and report the best action
+pub fn best_move(state: &GameState, depth: usize) -> SearchResult {
+    Search::new(SearchLimits::new().depth(depth)).run(state)
+}
+
+// The deepest iteration a search will attempt when nothing else stops it
+pub const MAX_DEPTH: usize = 64;
+
+// Searches without a table of their own get one this small, so that
+// short searches like best_move's don't pay for a big one. Longer
+// searches should be given a bigger table that outlives them.
+pub const SEARCH_TABLE_MEGABYTES: usize = 1;
+
+// Quiescence search gives up on lines longer than this
+const MAX_PLY: i32 = 128;
+
+// Selective search parameters
+const NULL_MOVE_MIN_DEPTH: usize = 3;
+const REVERSE_FUTILITY_DEPTH: usize = 3;
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+const FUTILITY_MARGINS: [i32; 3] = [0, 200, 450];
+const LATE_MOVE_INDEX: usize = 3;
+const LATE_MOVE_MIN_DEPTH: usize = 3;
+const HISTORY_LIMIT: i32 = 50_000;
+
+// Time held back from every clock allocation to cover communication delays
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+
+// When no moves-to-go is given, assume the game lasts this many more moves
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+// Conditions under which a search stops. Any number of them can be
+// combined, and the search ends as soon as the first one is met.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct SearchLimits {
+    depth: Option<usize>,
+    nodes: Option<u64>,
+    move_time: Option<Duration>,
+    white_time: Option<Duration>,
+    black_time: Option<Duration>,
+    white_increment: Duration,
+    black_increment: Duration,
+    moves_to_go: Option<u32>,
+}
+
+// How long a search may take. No new iteration starts after `soft` has
+// elapsed, and an iteration in progress is abandoned at `hard`.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct TimeBudget {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+impl SearchLimits {
+    // No limits at all: search until MAX_DEPTH or until stopped
+    pub fn new() -> SearchLimits {
+        SearchLimits::default()
+    }
+
+    pub fn depth(mut self, depth: usize) -> SearchLimits {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn nodes(mut self, nodes: u64) -> SearchLimits {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    pub fn move_time(mut self, time: Duration) -> SearchLimits {
+        self.move_time = Some(time);
+        self
+    }
+
+    // Time remaining on each player's clock, and what each gains per move
+    pub fn clock(
+        mut self,
+        white_time: Duration,
+        black_time: Duration,
+        white_increment: Duration,
+        black_increment: Duration,
+    ) -> SearchLimits {
+        self.white_time = Some(white_time);
+        self.black_time = Some(black_time);
+        self.white_increment = white_increment;
+        self.black_increment = black_increment;
+        self
+    }
+
+    // Moves left until the next time control
+    pub fn moves_to_go(mut self, moves: u32) -> SearchLimits {
+        self.moves_to_go = Some(moves);
+        self
+    }
+
+    // The same limits without the move time or clocks
+    pub fn without_time(mut self) -> SearchLimits {
+        self.move_time = None;
+        self.white_time = None;
+        self.black_time = None;
+        self
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH)
+    }
+
+    pub fn max_nodes(&self) -> Option<u64> {
+        self.nodes
+    }
+
+    // Decide how long `color` may think. A fixed move time is used as is.
+    // Otherwise the remaining clock is shared among the moves left to play,
+    // with most of the increment added on top.
+    pub fn time_budget(&self, color: Color) -> Option<TimeBudget> {
+        if let Some(time) = self.move_time {
+            return Some(TimeBudget { soft: time, hard: time });
+        }
+
+        let (remaining, increment) = match color {
+            White => (self.white_time?, self.white_increment),
+            Black => (self.black_time?, self.black_increment),
+        };
+
+        let available = remaining.saturating_sub(MOVE_OVERHEAD);
+        let moves_to_go = self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let target = remaining / moves_to_go + increment * 3 / 4;
+
+        let soft = target.min(available);
+        let hard = (target * 3).min(available);
+        Some(TimeBudget { soft, hard })
+    }
+}
+
+// Progress reported after each completed iteration
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SearchInfo {
+    // Which of the best lines this is, counting from 1
+    pub line: usize,
+    pub depth: usize,
+    pub score: i32,
+    pub nodes: u64,
+    pub nodes_per_second: u64,
+    pub elapsed: Duration,
+    pub principal_variation: Vec<String>,
+    pub principal_variation_moves: Vec<ChessMove>,
+}
+
+// The techniques that let the search skip or shorten unpromising lines,
+// and the move ordering heuristics that make them effective. All are
+// enabled by default. Each can be turned off to measure what it's worth.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct SearchFeatures {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub futility_pruning: bool,
+    pub reverse_futility_pruning: bool,
+    pub check_extensions: bool,
+    pub killer_moves: bool,
+    pub history_heuristic: bool,
+    pub countermoves: bool,
+}
+
+impl SearchFeatures {
+    // A plain alpha-beta search
+    pub fn none() -> SearchFeatures {
+        SearchFeatures {
+            null_move: false,
+            late_move_reductions: false,
+            futility_pruning: false,
+            reverse_futility_pruning: false,
+            check_extensions: false,
+            killer_moves: false,
+            history_heuristic: false,
+            countermoves: false,
+        }
+    }
+}
+
+impl Default for SearchFeatures {
+    fn default() -> SearchFeatures {
+        SearchFeatures {
+            null_move: true,
+            late_move_reductions: true,
+            futility_pruning: true,
+            reverse_futility_pruning: true,
+            check_extensions: true,
+            killer_moves: true,
+            history_heuristic: true,
+            countermoves: true,
+        }
+    }
+}
+
+pub type IterationCallback = Box<dyn FnMut(&SearchInfo) + Send>;
+
+// An iterative deepening search. Each iteration searches one ply deeper
+// than the last, until a limit is reached or the stop flag is raised.
+// The result always comes from the deepest iteration that finished.
+pub struct Search {
+    limits: SearchLimits,
+    stop: Arc<AtomicBool>,
+    on_iteration: Option<IterationCallback>,
+    table: Arc<TranspositionTable>,
+    features: SearchFeatures,
+    evaluator: Arc<dyn Evaluator>,
+    threads: usize,
+    lines: usize,
+    root_moves: Option<Vec<ChessMove>>,
+}
+
+impl Search {
+    pub fn new(limits: SearchLimits) -> Search {
+        Search {
+            limits,
+            stop: Arc::new(AtomicBool::new(false)),
+            on_iteration: None,
+            table: Arc::new(TranspositionTable::new(SEARCH_TABLE_MEGABYTES)),
+            features: SearchFeatures::default(),
+            evaluator: Arc::new(ClassicalEvaluator::default()),
+            threads: 1,
+            lines: 1,
+            root_moves: None,
+        }
+    }
+
+    // Only consider these moves in the position being searched. Moves
+    // that aren't legal there are ignored, and if none of them are, every
+    // move is considered.
+    pub fn with_root_moves(mut self, moves: Vec<ChessMove>) -> Search {
+        self.root_moves = Some(moves);
+        self
+    }
+
+    // Find the best `lines` lines of play instead of only the best one.
+    // Each line starts with a different move, and is searched as if the
+    // moves starting the better lines weren't available.
+    pub fn with_multi_pv(mut self, lines: usize) -> Search {
+        self.lines = lines.max(1);
+        self
+    }
+
+    // Search with `threads` threads in total. Helper threads search the
+    // same position independently, and help the main thread only through
+    // what they leave in the shared transposition table. With one thread,
+    // the default, searches are deterministic.
+    pub fn with_threads(mut self, threads: usize) -> Search {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // Score leaf positions with `evaluator` instead of the classical
+    // evaluation
+    pub fn with_evaluator(mut self, evaluator: Arc<dyn Evaluator>) -> Search {
+        self.evaluator = evaluator;
+        self
+    }
+
+    // Choose which selective search techniques to use
+    pub fn with_features(mut self, features: SearchFeatures) -> Search {
+        self.features = features;
+        self
+    }
+
+    // Share a transposition table with other searches, such as those for
+    // later moves of the same game
+    pub fn with_transposition_table(mut self, table: Arc<TranspositionTable>) -> Search {
+        self.table = table;
+        self
+    }
+
+    pub fn transposition_table(&self) -> Arc<TranspositionTable> {
+        Arc::clone(&self.table)
+    }
+
+    // Share a stop flag with other threads. Setting it to true ends the
+    // search as soon as possible.
+    pub fn with_stop_flag(mut self, stop: Arc<AtomicBool>) -> Search {
+        self.stop = stop;
+        self
+    }
+
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    pub fn on_iteration<F>(mut self, callback: F) -> Search
+    where
+        F: FnMut(&SearchInfo) + Send + 'static,
+    {
+        self.on_iteration = Some(Box::new(callback));
+        self
+    }
+
+    pub fn run(&mut self, state: &GameState) -> SearchResult {
+        let budget = self.limits.time_budget(state.to_move);
+        self.table.new_search();
+
+        let mut searcher = self.searcher(budget, self.limits.max_nodes(), Arc::clone(&self.stop));
+        if self.threads == 1 {
+            return self.iterate(state, budget, &mut searcher);
+        }
+
+        // Helpers stop when the main thread does
+        let helpers_stop = Arc::new(AtomicBool::new(false));
+        let helpers: Vec<Searcher> = (1..self.threads)
+            .map(|_| self.searcher(budget, None, Arc::clone(&helpers_stop)))
+            .collect();
+        let max_depth = self.limits.max_depth();
+        let root = *state;
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = helpers
+                .into_iter()
+                .enumerate()
+                .map(|(index, mut helper)| {
+                    scope.spawn(move || {
+                        helper.help(&root, max_depth, index + 1);
+                        helper.nodes
+                    })
+                })
+                .collect();
+
+            let mut result = self.iterate(state, budget, &mut searcher);
+            helpers_stop.store(true, Ordering::Relaxed);
+            for handle in handles {
+                result.nodes += handle.join().unwrap();
+            }
+            result
+        })
+    }
+
+    fn searcher(
+        &self,
+        budget: Option<TimeBudget>,
+        node_limit: Option<u64>,
+        stop: Arc<AtomicBool>,
+    ) -> Searcher {
+        Searcher {
+            nodes: 0,
+            started: Instant::now(),
+            deadline: budget.map(|budget| budget.hard),
+            node_limit,
+            stop,
+            aborted: false,
+            root_hint: None,
+            table: Arc::clone(&self.table),
+            features: self.features,
+            evaluator: Arc::clone(&self.evaluator),
+            tracker: self.evaluator.tracker(),
+            excluded: vec![],
+            root_moves: self.root_moves.clone(),
+            root_depth: 0,
+            killers: vec![[None; 2]; MAX_PLY as usize + 1],
+            history: [[0; 64]; 12],
+            countermoves: [[None; 64]; 12],
+            previous: vec![None; MAX_PLY as usize + 2],
+        }
+    }
+
+    // The main thread's iterative deepening loop, which decides when the
+    // search ends and what it returns
+    fn iterate(
+        &mut self,
+        state: &GameState,
+        budget: Option<TimeBudget>,
+        searcher: &mut Searcher,
+    ) -> SearchResult {
+        let mut completed: Option<(usize, Vec<Line>)> = None;
+        let mut root_moves = legal_chess_moves(state);
+        if let Some(allowed) = searcher.root_moves_in(&root_moves) {
+            root_moves.retain(|action| allowed.contains(action));
+        }
+        let line_count = self.lines.min(root_moves.len()).max(1);
+
+        for depth in 1..=self.limits.max_depth() {
+            searcher.root_depth = depth;
+            searcher.excluded.clear();
+            let mut lines = vec![];
+
+            while lines.len() < line_count {
+                let mut pv = vec![];
+                if let Some(tracker) = searcher.tracker.as_mut() {
+                    tracker.reset(state);
+                }
+                let score = searcher.negamax(state, depth, 0, -INFINITY, INFINITY, &mut pv);
+                if searcher.aborted {
+                    if lines.is_empty() && !pv.is_empty() {
+                        lines.push((score, pv));
+                    }
+                    break;
+                }
+                searcher.extend_from_table(state, depth, score, &mut pv);
+                if let Some(first) = pv.first() {
+                    searcher.excluded.push(*first);
+                }
+                lines.push((score, pv));
+            }
+
+            if searcher.aborted {
+                // A partial first iteration is still better than nothing
+                if completed.is_none() && !lines.is_empty() {
+                    completed = Some((depth, lines));
+                }
+                break;
+            }
+
+            // Later lines can score better than earlier ones when the search
+            // is unstable
+            lines.sort_by_key(|(score, _)| -score);
+            searcher.root_hint = lines[0].1.first().copied();
+            let elapsed = searcher.started.elapsed();
+
+            if let Some(callback) = self.on_iteration.as_mut() {
+                for (index, (score, pv)) in lines.iter().enumerate() {
+                    callback(&SearchInfo {
+                        line: index + 1,
+                        depth,
+                        score: *score,
+                        nodes: searcher.nodes,
+                        nodes_per_second: nodes_per_second(searcher.nodes, elapsed),
+                        elapsed,
+                        principal_variation: algebraic_line(state, pv),
+                        principal_variation_moves: pv.clone(),
+                    });
+                }
+            }
+
+            let (score, no_moves) = (lines[0].0, lines[0].1.is_empty());
+            completed = Some((depth, lines));
+
+            // Nothing more to learn once there are no moves or mate is found
+            if no_moves || is_mate_score(score) {
+                break;
+            }
+            if let Some(budget) = budget {
+                if elapsed >= budget.soft {
+                    break;
+                }
+            }
+        }
+
+        let (depth, lines) = match completed {
+            Some(completed) => completed,
+            None => {
+                let (depth, score, pv) = fallback_line(state, self.evaluator.as_ref());
+                (depth, vec![(score, pv)])
+            },
+        };
+        let (score, pv) = &lines[0];
+
+        SearchResult {
+            best_move: pv.first().copied(),
+            score: *score,
+            depth,
+            nodes: searcher.nodes,
+            principal_variation: algebraic_line(state, pv),
+            principal_variation_moves: pv.clone(),
+            lines: lines
+                .iter()
+                .filter(|(_, pv)| !pv.is_empty())
+                .map(|(score, pv)| SearchLine {
+                    first_move: pv[0],
+                    score: *score,
+                    depth,
+                    principal_variation: algebraic_line(state, pv),
+                    principal_variation_moves: pv.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+// A score and the moves that lead to it
+type Line = (i32, Vec<ChessMove>);
+
+// When a search is stopped before finishing a single iteration, play
+// any legal action rather than none
+fn fallback_line(state: &GameState, evaluator: &dyn Evaluator) -> (usize, i32, Vec<ChessMove>) {
+    let actions = legal_chess_moves(state);
+    let pv = actions.first().map(|action| vec![*action]).unwrap_or_default();
+    (0, evaluator.evaluate(state), pv)
+}
+
+fn nodes_per_second(nodes: u64, elapsed: Duration) -> u64 {
+    let micros = elapsed.as_micros().max(1) as u64;
+    nodes.saturating_mul(1_000_000) / micros
+}
+
+struct Searcher {
+    nodes: u64,
+    started: Instant,
+    deadline: Option<Duration>,
+    node_limit: Option<u64>,
+    stop: Arc<AtomicBool>,
+    aborted: bool,
+    // The best root action of the previous iteration, which is searched first
+    root_hint: Option<ChessMove>,
+    table: Arc<TranspositionTable>,
+    features: SearchFeatures,
+    evaluator: Arc<dyn Evaluator>,
+    // Kept in step with the line being searched, for evaluators that
+    // update their work incrementally
+    tracker: Option<Box<dyn EvaluationTracker>>,
+    // Root moves left out of the search, because they start better lines
+    // already found by a MultiPV search
+    excluded: Vec<ChessMove>,
+    // The only root moves to search, if restricted
+    root_moves: Option<Vec<ChessMove>>,
+    root_depth: usize,
+    // Quiet moves that recently caused cutoffs at each ply
+    killers: Vec<[Option<ChessMove>; 2]>,
+    // How often quiet moves have caused cutoffs, by piece and destination
+    history: [[i32; 64]; 12],
+    // The quiet move that last refuted each move, by piece and destination
+    countermoves: [[Option<ChessMove>; 64]; 12],
+    // The move that led to the position at each ply, None for a null move
+    previous: Vec<Option<(usize, usize)>>,
+}
+
+impl Searcher {
+    fn evaluate(&self, state: &GameState) -> i32 {
+        match self.tracker.as_ref() {
+            Some(tracker) => tracker.evaluate(state),
+            None => self.evaluator.evaluate(state),
+        }
+    }
+
+    fn make(&mut self, before: &GameState, after: &GameState) {
+        if let Some(tracker) = self.tracker.as_mut() {
+            tracker.make(before, after);
+        }
+    }
+
+    fn unmake(&mut self) {
+        if let Some(tracker) = self.tracker.as_mut() {
+            tracker.unmake();
+        }
+    }
+
+    // A helper thread's iterative deepening loop. Odd helpers skip the
+    // first depth, so that not every thread searches the same tree at the
+    // same time as the main thread.
+    fn help(&mut self, state: &GameState, max_depth: usize, helper: usize) {
+        for depth in (1 + helper % 2)..=max_depth {
+            self.root_depth = depth;
+            if let Some(tracker) = self.tracker.as_mut() {
+                tracker.reset(state);
+            }
+            let mut pv = vec![];
+            self.negamax(state, depth, 0, -INFINITY, INFINITY, &mut pv);
+            if self.aborted || pv.is_empty() {
+                break;
+            }
+            self.root_hint = pv.first().copied();
+        }
+    }
+
+    // The moves the root is restricted to, unless none of them are legal,
+    // in which case every move is searched
+    fn root_moves_in(&self, legal: &[ChessMove]) -> Option<Vec<ChessMove>> {
+        match &self.root_moves {
+            Some(moves) if legal.iter().any(|action| moves.contains(action)) => Some(moves.clone()),
+            _ => None,
+        }
+    }
+
+    fn should_abort(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        let out_of_nodes = match self.node_limit {
+            Some(limit) => self.nodes >= limit,
+            None => false,
+        };
+        let out_of_time = match self.deadline {
+            Some(deadline) => self.started.elapsed() >= deadline,
+            None => false,
+        };
+        if out_of_nodes || out_of_time || self.stop.load(Ordering::Relaxed) {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+
+    // Score `state` for the player to move, filling `pv` with the line of
+    // play that leads to that score. Once the search is aborted, the
+    // returned score is meaningless.
+    fn negamax(
+        &mut self,
+        state: &GameState,
+        mut depth: usize,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<ChessMove>,
+    ) -> i32 {
+        pv.clear();
+        if self.should_abort() {
+            return 0;
+        }
+
+        let in_check = color_is_checked(state.to_move, state);
+
+        // Don't let a checking sequence drop straight into quiescence
+        if in_check && self.features.check_extensions && ply < 2 * self.root_depth as i32 {
+            depth += 1;
+        }
+
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiesce(state, ply, alpha, beta);
+        }
+        self.nodes += 1;
+
+        let key = state.hash();
+        let mut hash_move = None;
+        if let Some(entry) = self.table.probe(key) {
+            hash_move = entry.best_move;
+            if ply > 0 && entry.depth >= depth {
+                let score = score_from_table(entry.score, ply);
+                let usable = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if usable {
+                    if entry.bound == Bound::Exact {
+                        pv.extend(hash_move);
+                    }
+                    return score;
+                }
+            }
+        }
+
+        let pv_node = beta - alpha > 1;
+        let static_eval = self.evaluate(state);
+        let not_mate_bound = !is_mate_score(alpha) && !is_mate_score(beta);
+
+        // Reverse futility pruning: far enough above beta that losing
+        // a margin per remaining ply would still leave it above beta
+        if self.features.reverse_futility_pruning
+            && ply > 0
+            && !in_check
+            && not_mate_bound
+            && depth <= REVERSE_FUTILITY_DEPTH
+            && static_eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= beta
+        {
+            return static_eval;
+        }
+
+        // Null-move pruning: if passing still fails high, a real move will
+        // too. Passing is never better in zugzwang, which is common when
+        // only kings and pawns are left, so those positions are excluded.
+        let after_null_move = ply > 0 && self.previous[ply as usize].is_none();
+        if self.features.null_move
+            && ply > 0
+            && !after_null_move
+            && !in_check
+            && not_mate_bound
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && static_eval >= beta
+            && has_non_pawn_material(state)
+        {
+            let reduction = if depth > 6 { 3 } else { 2 };
+            let mut passed = *state;
+            passed.to_move = if state.to_move == White { Black } else { White };
+            passed.en_passant_square = None;
+
+            self.previous[ply as usize + 1] = None;
+            let mut null_pv = vec![];
+            self.make(state, &passed);
+            let score = -self.negamax(&passed, depth - 1 - reduction, ply + 1, -beta, -beta + 1, &mut null_pv);
+            self.unmake();
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+        }
+
+        let mut actions = legal_chess_moves(state);
+        if actions.is_empty() {
+            if in_check {
+                return -(MATE_SCORE - ply);
+            }
+            return 0;
+        }
+        let restricted = ply == 0 && (!self.excluded.is_empty() || self.root_moves.is_some());
+        if restricted {
+            if let Some(moves) = self.root_moves_in(&actions) {
+                actions.retain(|action| moves.contains(action));
+            }
+            actions.retain(|action| !self.excluded.contains(action));
+        }
+
+        // The move that was best before is the most likely to be best again
+        let preferred = if ply == 0 { self.root_hint.or(hash_move) } else { hash_move };
+        self.order_moves(state, ply, preferred, &mut actions);
+
+        // Futility pruning: near the leaves, quiet moves can't raise
+        // a hopeless score above alpha
+        let futile = self.features.futility_pruning
+            && ply > 0
+            && !in_check
+            && not_mate_bound
+            && depth < FUTILITY_MARGINS.len()
+            && static_eval + FUTILITY_MARGINS[depth] <= alpha;
+
+        let original_alpha = alpha;
+        let mut child_pv = vec![];
+        for (index, action) in actions.iter().enumerate() {
+            let next_state = action.apply(state);
+            let quiet = is_quiet(action);
+            let gives_check = color_is_checked(next_state.to_move, &next_state);
+
+            if futile && quiet && !gives_check && index > 0 {
+                continue;
+            }
+
+            self.previous[ply as usize + 1] = Some(move_key(state, action));
+
+            // Late move reductions: quiet moves ordered late are unlikely to
+            // be best, so search them shallower first, and only search them
+            // fully if they turn out better than expected
+            let reduce = self.features.late_move_reductions
+                && !pv_node
+                && index >= LATE_MOVE_INDEX
+                && depth >= LATE_MOVE_MIN_DEPTH
+                && quiet
+                && !in_check
+                && !gives_check
+                && !self.is_killer(ply, action);
+
+            // After the first move, each move only has to be shown to be no
+            // better than alpha, which a zero-width window does cheaply. Only
+            // moves that fail that test are searched again with the full window.
+            self.make(state, &next_state);
+            let mut score;
+            if index == 0 {
+                score = -self.negamax(&next_state, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            } else {
+                let reduction = if !reduce {
+                    0
+                } else if index >= 2 * LATE_MOVE_INDEX {
+                    2
+                } else {
+                    1
+                };
+                score = -self.negamax(
+                    &next_state,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    &mut child_pv,
+                );
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(&next_state, depth - 1, ply + 1, -alpha - 1, -alpha, &mut child_pv);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(&next_state, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+                }
+            }
+            self.unmake();
+            if self.aborted {
+                return alpha;
+            }
+
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(*action);
+                pv.extend_from_slice(&child_pv);
+            }
+            if alpha >= beta {
+                if quiet {
+                    self.record_cutoff(state, ply, depth, action);
+                }
+                break;
+            }
+        }
+
+        let bound = if alpha >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        let best_move = if alpha > original_alpha { pv.first().copied() } else { None };
+        // What was learned without some of the moves isn't true of the position
+        if !restricted {
+            self.table.store(key, depth, bound, score_to_table(alpha, ply), best_move);
+        }
+
+        alpha
+    }
+
+    // Remember a quiet move that refuted the position, for ordering moves
+    // in positions searched later
+    fn record_cutoff(&mut self, state: &GameState, ply: i32, depth: usize, action: &ChessMove) {
+        let ply = ply as usize;
+        if self.features.killer_moves && self.killers[ply][0] != Some(*action) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(*action);
+        }
+        if self.features.history_heuristic {
+            let (piece, to) = move_key(state, action);
+            let bonus = (depth * depth) as i32;
+            self.history[piece][to] = (self.history[piece][to] + bonus).min(HISTORY_LIMIT);
+        }
+        if self.features.countermoves {
+            if let Some((piece, to)) = self.previous[ply] {
+                self.countermoves[piece][to] = Some(*action);
+            }
+        }
+    }
+
+    fn is_killer(&self, ply: i32, action: &ChessMove) -> bool {
+        self.features.killer_moves && self.killers[ply as usize].contains(&Some(*action))
+    }
+
+    // Search the preferred move first, then captures and promotions, then
+    // killers and the countermove, then the remaining quiet moves by history
+    fn order_moves(
+        &self,
+        state: &GameState,
+        ply: i32,
+        preferred: Option<ChessMove>,
+        actions: &mut [ChessMove],
+    ) {
+        let countermove = match self.previous[ply as usize] {
+            Some((piece, to)) if self.features.countermoves => self.countermoves[piece][to],
+            _ => None,
+        };
+
+        actions.sort_by_cached_key(|action| {
+            let score = if Some(*action) == preferred {
+                1_000_000
+            } else if !is_quiet(action) {
+                100_000 + move_order_score(state, action)
+            } else if self.is_killer(ply, action) {
+                90_000
+            } else if Some(*action) == countermove {
+                80_000
+            } else if self.features.history_heuristic {
+                let (piece, to) = move_key(state, action);
+                self.history[piece][to]
+            } else {
+                0
+            };
+            -score
+        });
+    }
+
+    // Keep searching captures and promotions past the nominal depth, so
+    // that positions are only evaluated once they are quiet. Without this,
+    // the search happily takes a defended pawn with its queen on the last
+    // ply because it can't see the recapture.
+    fn quiesce(&mut self, state: &GameState, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_abort() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        // Every reply to check has to be considered, not just captures
+        if color_is_checked(state.to_move, state) {
+            let actions = legal_chess_moves(state);
+            if actions.is_empty() {
+                return -(MATE_SCORE - ply);
+            }
+            if ply >= MAX_PLY {
+                return self.evaluate(state);
+            }
+            for action in actions.iter() {
+                let next_state = action.apply(state);
+                self.make(state, &next_state);
+                let score = -self.quiesce(&next_state, ply + 1, -beta, -alpha);
+                self.unmake();
+                if self.aborted {
+                    return alpha;
+                }
+                alpha = alpha.max(score);
+                if alpha >= beta {
+                    break;
+                }
+            }
+            return alpha;
+        }
+
+        // The player to move can always decline to capture anything
+        let stand_pat = self.evaluate(state);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut actions = noisy_moves(state);
+        actions.sort_by_key(|action| -move_order_score(state, action));
+
+        for action in actions.iter() {
+            // Captures that lose material can't improve on standing pat
+            if let ChessMove::Capture(capture) = action {
+                if see(state, capture) < 0 {
+                    continue;
+                }
+            }
+            let next_state = action.apply(state);
+            self.make(state, &next_state);
+            let score = -self.quiesce(&next_state, ply + 1, -beta, -alpha);
+            self.unmake();
+            if self.aborted {
+                return alpha;
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        alpha
+    }
+
+    // Lines cut short by table hits are completed with the best moves
+    // the table remembers, for as long as those moves stay legal. Mating
+    // lines run to the mate, which other threads may have found deeper
+    // than this iteration searched.
+    fn extend_from_table(&self, state: &GameState, depth: usize, score: i32, pv: &mut Vec<ChessMove>) {
+        let mut state = *state;
+        for action in pv.iter() {
+            state = action.apply(&state);
+        }
+        let length = if is_mate_score(score) {
+            ((MATE_SCORE - score.abs()) as usize).min(MAX_PLY as usize)
+        } else {
+            depth
+        };
+        let table = &self.table;
+        while pv.len() < length {
+            let action = match table.probe(state.hash()).and_then(|entry| entry.best_move) {
+                Some(action) => action,
+                None => break,
+            };
+            if !action.is_legal(&state) {
+                break;
+            }
+            pv.push(action);
+            state = action.apply(&state);
+        }
+    }
+}
+
+fn is_quiet(action: &ChessMove) -> bool {
+    matches!(action, ChessMove::Move(_) | ChessMove::Castle(_))
+}
+
+fn has_non_pawn_material(state: &GameState) -> bool {
+    state.squares.iter().flatten().any(|piece| {
+        piece.color == state.to_move && piece.name != Pawn && piece.name != King
+    })
+}
+
+// Identify an action by the piece that moves and where it lands, which is
+// how the history and countermove tables are indexed
+fn move_key(state: &GameState, action: &ChessMove) -> (usize, usize) {
+    let (from, to) = match action {
+        ChessMove::Move(action) => (action.from, action.to),
+        ChessMove::Capture(action) => (action.with, action.on),
+        ChessMove::EnPassant(action) => (action.with, state.en_passant_square.unwrap_or(0)),
+        ChessMove::Promotion(action) => (action.moving_from, action.to),
+        ChessMove::Castle(castle) => match (state.to_move, castle.direction) {
+            (White, Kingside) => (4, 6),
+            (White, Queenside) => (4, 2),
+            (Black, Kingside) => (60, 62),
+            (Black, Queenside) => (60, 58),
+        },
+    };
+    match state.squares[from] {
+        Some(piece) => (piece_index(&piece), to),
+        None => (0, to),
+    }
+}
+
+// Captures, en-passants and promotions
+fn noisy_moves(state: &GameState) -> Vec<ChessMove> {
+    let mut results = vec![];
+    for action in legal_captures(state) {
+        results.push(ChessMove::Capture(action));
+    }
+    for action in legal_en_passants(state) {
+        results.push(ChessMove::EnPassant(action));
+    }
+    for action in legal_promotions(state) {
+        results.push(ChessMove::Promotion(action));
+    }
+    results
+}
+
+// Captures are ordered most valuable victim first, then least valuable
+// attacker first, with promotions just below the best captures
+fn move_order_score(state: &GameState, action: &ChessMove) -> i32 {
+    match action {
+        ChessMove::Capture(capture) => {
+            let victim = state.squares[capture.on].unwrap().name;
+            let attacker = state.squares[capture.with].unwrap().name;
+            10 * piece_value(&victim) as i32 - piece_value(&attacker) as i32 + 100
+        },
+        ChessMove::Promotion(promotion) => 90 + piece_value(&promotion.pawn_becomes) as i32,
+        ChessMove::EnPassant(_) => 109,
+        _ => 0,
+    }
+}
+
+// Describe a sequence of actions from `state` in algebraic notation
+pub fn algebraic_line(state: &GameState, actions: &[ChessMove]) -> Vec<String> {
+    let mut state = *state;
+    let mut line = vec![];
+    for action in actions.iter() {
+        line.push(action.as_algebraic_notation(&state));
+        state = action.apply(&state);
+    }
+    line
+}
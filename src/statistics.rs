@@ -0,0 +1,194 @@
+// The single responsibility of this module is to judge match results:
+// how strong one player is relative to another, how sure we can be, and
+// whether a match has gone on long enough to decide between two
+// hypotheses.
+
+// The normal quantile for a two-sided 95% confidence interval
+const Z_95: f64 = 1.959_963_984_540_054;
+
+// Added to every result count before estimating variances, so that
+// matches without draws or losses don't have zero variance
+const PRIOR_COUNT: f64 = 1e-3;
+
+// An Elo difference with a 95% confidence interval of elo +/- error, and
+// the likelihood of superiority, the probability that the difference is
+// positive
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct EloEstimate {
+    pub elo: f64,
+    pub error: f64,
+    pub los: f64,
+}
+
+// The expected score of a player `elo` points stronger
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// Estimate from wins, draws and losses. Games are assumed independent,
+// which overstates the error of matches that play each opening twice.
+pub fn elo_from_wdl(wins: usize, draws: usize, losses: usize) -> EloEstimate {
+    estimate(&[(1.0, wins), (0.5, draws), (0.0, losses)])
+}
+
+// Estimate from pairs of games played with the same opening and colours
+// reversed. `pairs[i]` is how many pairs the player scored i half points
+// in, from 0 for two losses to 4 for two wins.
+pub fn elo_from_pentanomial(pairs: &[usize; 5]) -> EloEstimate {
+    estimate(&pentanomial_outcomes(pairs))
+}
+
+// The likelihood of superiority from wins and losses alone, since draws
+// say nothing about which player is stronger
+pub fn los(wins: usize, losses: usize) -> f64 {
+    if wins + losses == 0 {
+        return 0.5;
+    }
+    normal_cdf((wins as f64 - losses as f64) / ((wins + losses) as f64).sqrt())
+}
+
+// Each pair scored as the mean of its two games
+fn pentanomial_outcomes(pairs: &[usize; 5]) -> [(f64, usize); 5] {
+    let mut outcomes = [(0.0, 0); 5];
+    for (half_points, count) in pairs.iter().enumerate() {
+        outcomes[half_points] = (half_points as f64 / 4.0, *count);
+    }
+    outcomes
+}
+
+// The mean score, its variance per observation, and the number of
+// observations, for outcomes given as (score, count)
+fn moments(outcomes: &[(f64, usize)]) -> (f64, f64, f64) {
+    let total: f64 = outcomes.iter().map(|(_, count)| *count as f64 + PRIOR_COUNT).sum();
+    let mean = outcomes
+        .iter()
+        .map(|(score, count)| score * (*count as f64 + PRIOR_COUNT))
+        .sum::<f64>() / total;
+    let variance = outcomes
+        .iter()
+        .map(|(score, count)| (score - mean).powi(2) * (*count as f64 + PRIOR_COUNT))
+        .sum::<f64>() / total;
+    let observations: usize = outcomes.iter().map(|(_, count)| count).sum();
+    (mean, variance, observations as f64)
+}
+
+fn estimate(outcomes: &[(f64, usize)]) -> EloEstimate {
+    let (mean, variance, observations) = moments(outcomes);
+    if observations == 0.0 {
+        return EloEstimate { elo: 0.0, error: f64::INFINITY, los: 0.5 };
+    }
+    let standard_error = (variance / observations).sqrt();
+
+    let lower = score_to_elo((mean - Z_95 * standard_error).max(0.0));
+    let upper = score_to_elo((mean + Z_95 * standard_error).min(1.0));
+    EloEstimate {
+        elo: score_to_elo(mean),
+        error: (upper - lower) / 2.0,
+        los: normal_cdf((mean - 0.5) / standard_error),
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum SprtDecision {
+    // The difference is elo0 or less
+    AcceptH0,
+    // The difference is elo1 or more
+    AcceptH1,
+    Continue,
+}
+
+// A sequential probability ratio test between the hypotheses that one
+// player is elo0 and elo1 stronger than the other, wrongly accepting H1
+// with probability alpha and wrongly accepting H0 with probability beta
+#[derive(Debug)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Sprt {
+        Sprt::new(0.0, 5.0, 0.05, 0.05)
+    }
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> Sprt {
+        Sprt { elo0, elo1, alpha, beta }
+    }
+
+    // The log-likelihood ratios below which H0 is accepted and above which
+    // H1 is
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn llr_wdl(&self, wins: usize, draws: usize, losses: usize) -> f64 {
+        self.llr(&[(1.0, wins), (0.5, draws), (0.0, losses)])
+    }
+
+    // `pairs` is counted as for elo_from_pentanomial
+    pub fn llr_pentanomial(&self, pairs: &[usize; 5]) -> f64 {
+        self.llr(&pentanomial_outcomes(pairs))
+    }
+
+    pub fn decision(&self, llr: f64) -> SprtDecision {
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtDecision::AcceptH0
+        } else if llr >= upper {
+            SprtDecision::AcceptH1
+        } else {
+            SprtDecision::Continue
+        }
+    }
+
+    // The generalised SPRT approximation, treating the mean score as
+    // normally distributed with the observed variance
+    fn llr(&self, outcomes: &[(f64, usize)]) -> f64 {
+        let (mean, variance, observations) = moments(outcomes);
+        if observations == 0.0 {
+            return 0.0;
+        }
+        let score0 = elo_to_score(self.elo0);
+        let score1 = elo_to_score(self.elo1);
+        observations * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    if x.is_nan() {
+        return 0.5;
+    }
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+// Abramowitz and Stegun 7.1.26, accurate to about 1e-7
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let polynomial = t * (0.254_829_592
+        + t * (-0.284_496_736
+        + t * (1.421_413_741
+        + t * (-1.453_152_027
+        + t * 1.061_405_429))));
+    sign * (1.0 - polynomial * (-x * x).exp())
+}
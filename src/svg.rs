@@ -0,0 +1,231 @@
+// The single responsibility of this module is to draw GameStates as
+// self-contained SVG diagrams.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    PieceName,
+    Color,
+    Color::{White, Black},
+};
+
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+const LAST_MOVE: &str = "#cdd26a";
+const HIGHLIGHT: &str = "#e06c5a";
+const ARROW: &str = "#15781b";
+const MARGIN: usize = 20;
+
+// Pieces are drawn with the solid chess glyphs, filled white or black and
+// outlined, so both colors look alike apart from their fill.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SvgRenderer {
+    square_size: usize,
+    perspective: Color,
+    coordinates: bool,
+    last_move: Option<(usize, usize)>,
+    highlights: Vec<usize>,
+    arrows: Vec<(usize, usize)>,
+}
+
+impl SvgRenderer {
+    pub fn new() -> SvgRenderer {
+        SvgRenderer {
+            square_size: 45,
+            perspective: White,
+            coordinates: true,
+            last_move: None,
+            highlights: vec![],
+            arrows: vec![],
+        }
+    }
+
+    pub fn square_size(mut self, pixels: usize) -> SvgRenderer {
+        self.square_size = pixels;
+        self
+    }
+
+    // The color whose pieces are drawn at the bottom of the board
+    pub fn perspective(mut self, color: Color) -> SvgRenderer {
+        self.perspective = color;
+        self
+    }
+
+    pub fn coordinates(mut self, enabled: bool) -> SvgRenderer {
+        self.coordinates = enabled;
+        self
+    }
+
+    // Squares off the board are ignored, here and for highlights and
+    // arrows
+    pub fn last_move(mut self, from: usize, to: usize) -> SvgRenderer {
+        if from < 64 && to < 64 {
+            self.last_move = Some((from, to));
+        }
+        self
+    }
+
+    pub fn highlight(mut self, square: usize) -> SvgRenderer {
+        if square < 64 {
+            self.highlights.push(square);
+        }
+        self
+    }
+
+    pub fn arrow(mut self, from: usize, to: usize) -> SvgRenderer {
+        if from < 64 && to < 64 {
+            self.arrows.push((from, to));
+        }
+        self
+    }
+
+    pub fn render(&self, state: &GameState) -> String {
+        let size = self.square_size;
+        let margin = if self.coordinates { MARGIN } else { 0 };
+        let width = 8 * size + 2 * margin;
+        let mut output = String::new();
+
+        output.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" \
+             width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+            width,
+        ));
+        output.push_str(&format!(
+            "<defs><marker id=\"arrowhead\" viewBox=\"0 0 10 10\" refX=\"5\" refY=\"5\" \
+             markerWidth=\"3\" markerHeight=\"3\" orient=\"auto\">\
+             <path d=\"M0,0 L10,5 L0,10 z\" fill=\"{}\"/></marker></defs>\n",
+            ARROW,
+        ));
+
+        // Squares, with the last move and highlights painted over them
+        for square in 0..64 {
+            let (x, y) = self.square_origin(square, margin);
+            let is_light = (square / 8 + square % 8) % 2 == 1;
+            let fill = if is_light { LIGHT_SQUARE } else { DARK_SQUARE };
+            output.push_str(&rect(x, y, size, fill, None));
+        }
+        if let Some((from, to)) = self.last_move {
+            for square in [from, to].iter() {
+                let (x, y) = self.square_origin(*square, margin);
+                output.push_str(&rect(x, y, size, LAST_MOVE, Some(0.8)));
+            }
+        }
+        for square in self.highlights.iter() {
+            let (x, y) = self.square_origin(*square, margin);
+            output.push_str(&rect(x, y, size, HIGHLIGHT, Some(0.6)));
+        }
+
+        if self.coordinates {
+            output.push_str(&self.render_coordinates(margin));
+        }
+
+        for (square, maybe_piece) in state.squares.iter().enumerate() {
+            if let Some(piece) = maybe_piece {
+                let (x, y) = self.square_origin(square, margin);
+                let (fill, stroke) = match piece.color {
+                    White => ("#ffffff", "#000000"),
+                    Black => ("#000000", "#000000"),
+                };
+                output.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" \
+                     dominant-baseline=\"central\" fill=\"{}\" stroke=\"{}\" \
+                     stroke-width=\"1\" font-family=\"DejaVu Sans, Segoe UI Symbol, serif\">{}</text>\n",
+                    x + size / 2,
+                    y + size / 2,
+                    size * 4 / 5,
+                    fill,
+                    stroke,
+                    solid_glyph(piece.name),
+                ));
+            }
+        }
+
+        // Arrows run between square centers, stopping short of the target
+        // so the arrowhead doesn't cover the piece underneath
+        for (from, to) in self.arrows.iter() {
+            let (x1, y1) = self.square_center(*from, margin);
+            let (x2, y2) = self.square_center(*to, margin);
+            let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+            let shorten = if length > 0.0 { size as f64 * 0.3 / length } else { 0.0 };
+            output.push_str(&format!(
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" \
+                 stroke-width=\"{:.1}\" stroke-linecap=\"round\" opacity=\"0.8\" \
+                 marker-end=\"url(#arrowhead)\"/>\n",
+                x1,
+                y1,
+                x2 - (x2 - x1) * shorten,
+                y2 - (y2 - y1) * shorten,
+                ARROW,
+                size as f64 * 0.18,
+            ));
+        }
+
+        output.push_str("</svg>\n");
+        output
+    }
+
+    fn render_coordinates(&self, margin: usize) -> String {
+        let size = self.square_size;
+        let mut output = String::new();
+        for i in 0..8 {
+            let file = if self.perspective == White { i } else { 7 - i };
+            let rank = if self.perspective == White { 7 - i } else { i };
+            let center = margin + i * size + size / 2;
+            output.push_str(&label(center, 8 * size + margin + margin / 2, (b'a' + file as u8) as char));
+            output.push_str(&label(margin / 2, center, (b'1' + rank as u8) as char));
+        }
+        output
+    }
+
+    fn square_origin(&self, square: usize, margin: usize) -> (usize, usize) {
+        let (file, rank) = (square % 8, square / 8);
+        let (column, row) = match self.perspective {
+            White => (file, 7 - rank),
+            Black => (7 - file, rank),
+        };
+        (margin + column * self.square_size, margin + row * self.square_size)
+    }
+
+    fn square_center(&self, square: usize, margin: usize) -> (f64, f64) {
+        let (x, y) = self.square_origin(square, margin);
+        let half = self.square_size as f64 / 2.0;
+        (x as f64 + half, y as f64 + half)
+    }
+}
+
+impl Default for SvgRenderer {
+    fn default() -> SvgRenderer {
+        SvgRenderer::new()
+    }
+}
+
+fn rect(x: usize, y: usize, size: usize, fill: &str, opacity: Option<f64>) -> String {
+    let opacity = match opacity {
+        Some(opacity) => format!(" opacity=\"{}\"", opacity),
+        None => String::new(),
+    };
+    format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"{}/>\n",
+        x, y, size, size, fill, opacity,
+    )
+}
+
+fn label(x: usize, y: usize, text: char) -> String {
+    format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"12\" text-anchor=\"middle\" \
+         dominant-baseline=\"central\" font-family=\"sans-serif\" fill=\"#333333\">{}</text>\n",
+        x, y, text,
+    )
+}
+
+fn solid_glyph(name: PieceName) -> char {
+    match name {
+        PieceName::Pawn => '♟',
+        PieceName::Bishop => '♝',
+        PieceName::Knight => '♞',
+        PieceName::Rook => '♜',
+        PieceName::Queen => '♛',
+        PieceName::King => '♚',
+    }
+}
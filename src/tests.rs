@@ -7,6 +7,7 @@ use crate::utilities::{
     is_checkmate,
     is_stalemate,
     diagonal_path_is_obstructed,
+    legal_chess_moves,
 };
 
 use crate::actions::{
@@ -17,11 +18,14 @@ use crate::actions::{
     Castle,
     CastleDirection::{Kingside, Queenside},
     EnPassant,
+    ChessMove,
 };
 
 use crate::gamestate::{
     GameState,
     Placement,
+    DiagramError,
+    FenError,
 };
 
 use crate::pieces::{
@@ -41,6 +45,140 @@ use crate::notation::{
     square_index_to_algebraic,
     square_algebraic_to_index,
     fen_notation,
+    to_uci,
+    parse_uci_move,
+    parse_san,
+    UciMoveError,
+};
+
+use crate::validation::PositionError;
+
+use crate::rendering::TextRenderer;
+
+use crate::svg::SvgRenderer;
+
+use crate::search::{
+    best_move,
+    mate_distance,
+    MATE_SCORE,
+    Search,
+    SearchLimits,
+    SearchFeatures,
+    SearchResult,
+    TimeBudget,
+    SEARCH_TABLE_MEGABYTES,
+};
+
+use crate::transposition::{
+    TranspositionTable,
+    Bound,
+    score_to_table,
+    score_from_table,
+};
+
+use crate::exchange::see;
+
+use crate::evaluation::{
+    Evaluator,
+    MaterialEvaluator,
+    ClassicalEvaluator,
+    EvaluationWeights,
+    WeightsError,
+    game_phase,
+};
+
+use crate::nnue::{
+    Network,
+    NetworkError,
+    NnueEvaluator,
+    AccumulatorStack,
+    feature_index,
+    INPUT_SIZE,
+    ACTIVATION_LIMIT,
+};
+
+use crate::evaluation::EvaluationTracker;
+
+use crate::uci::UciEngine;
+
+use crate::cecp::CecpEngine;
+
+use crate::arena::{
+    TimeControl,
+    MoveRequest,
+    Player,
+    PlayerError,
+    SearchPlayer,
+    UciPlayer,
+    Match,
+    GameResult,
+    Termination,
+    read_epd_openings,
+    read_pgn_openings,
+};
+
+use crate::agents::{
+    Agent,
+    RandomAgent,
+    GreedyMaterialAgent,
+    CaptureAgent,
+    MinimaxAgent,
+    play_game,
+};
+
+use crate::environment::{
+    ChessEnv,
+    RewardScheme,
+    EnvironmentError,
+    ACTION_COUNT,
+    action_index,
+    index_to_action,
+    legal_action_mask,
+};
+
+use crate::policy::{
+    PolicyOrientation,
+    POLICY_SIZE,
+    move_to_policy_index,
+    policy_index_to_move,
+    policy_legal_mask,
+};
+
+use crate::planes::{
+    PlaneEncoder,
+    PositionHistory,
+};
+
+use crate::mcts::{
+    Mcts,
+    PolicyValueEvaluator,
+    UniformEvaluator,
+};
+
+use crate::statistics::{
+    Sprt,
+    SprtDecision,
+    elo_to_score,
+    score_to_elo,
+    elo_from_wdl,
+    elo_from_pentanomial,
+    los,
+};
+
+use crate::tuner::{
+    read_labelled_positions,
+    sigmoid,
+    Tuner,
+    TunerError,
+};
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::builder::{
+    GameStateBuilder,
+    BuilderError,
 };
 
 #[test]
@@ -1281,6 +1419,24 @@ fn legal_actions_includes_promotions_test() {
     }));
 }
 
+#[test]
+fn legal_actions_pawn_on_seventh_rank_only_promotes_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 0),
+        Placement::new(White, Pawn, 52),
+        Placement::new(Black, King, 40),
+    ]);
+
+    let actions = legal_actions(&state);
+    let pawn_actions: Vec<&Box<dyn Action>> = actions
+        .iter()
+        .filter(|action| action.apply(&state).squares[52].is_none())
+        .collect();
+    assert_eq!(4, pawn_actions.len());
+    assert!(pawn_actions.iter().all(|action| action.name() == "Promotion"));
+    assert!(!Move { from: 52, to: 60 }.is_legal(&state));
+}
+
 #[test]
 fn legal_actions_includes_all_legal_castles_by_white_test() {
     let mut state = GameState::with_placements(vec![
@@ -2103,3 +2259,2333 @@ fn fen_notation_default_state_test() {
     assert_eq!(output, expected);
 }
 
+
+#[test]
+fn default_state_is_valid_test() {
+    assert_eq!(Ok(()), GameState::new().validate());
+}
+
+#[test]
+fn validate_missing_and_extra_kings_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, King, 12),
+    ]);
+    let errors = state.validate().unwrap_err();
+    assert!(errors.contains(&PositionError::TooManyKings(White)));
+    assert!(errors.contains(&PositionError::MissingKing(Black)));
+}
+
+#[test]
+fn validate_pawn_on_back_rank_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(Black, King, 60),
+        Placement::new(White, Pawn, 58),
+        Placement::new(Black, Pawn, 1),
+    ]);
+    let errors = state.validate().unwrap_err();
+    assert_eq!(errors, vec![
+        PositionError::PawnOnBackRank(1),
+        PositionError::PawnOnBackRank(58),
+    ]);
+}
+
+#[test]
+fn validate_castling_rights_without_rook_test() {
+    let mut state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 0),
+        Placement::new(Black, King, 60),
+    ]);
+    state.white_can_castle_kingside = true;
+    state.white_can_castle_queenside = true;
+    let errors = state.validate().unwrap_err();
+    assert_eq!(errors, vec![
+        PositionError::CastlingWithoutRook(White, Kingside),
+    ]);
+}
+
+#[test]
+fn validate_en_passant_square_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Pawn, 12),
+        Placement::new(Black, King, 60),
+    ]);
+    let state = Move { from: 12, to: 28 }.apply(&state);
+    assert_eq!(Ok(()), state.validate());
+
+    // No pawn in front of the en-passant square
+    let mut state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(Black, King, 60),
+    ]);
+    state.en_passant_square = Some(44);
+    let errors = state.validate().unwrap_err();
+    assert_eq!(errors, vec![PositionError::InvalidEnPassantSquare(44)]);
+
+    // Off the board
+    state.en_passant_square = Some(64);
+    let errors = state.validate().unwrap_err();
+    assert_eq!(errors, vec![PositionError::InvalidEnPassantSquare(64)]);
+}
+
+#[test]
+fn validate_side_not_to_move_in_check_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 56),
+        Placement::new(Black, King, 60),
+    ]);
+    let errors = state.validate().unwrap_err();
+    assert_eq!(errors, vec![PositionError::OpponentInCheck(Black)]);
+}
+
+#[test]
+fn builder_refuses_invalid_position_test() {
+    let result = GameStateBuilder::new()
+        .placement(Placement::new(White, King, 4))
+        .build();
+    assert_eq!(
+        result.unwrap_err(),
+        BuilderError::InvalidPosition(vec![PositionError::MissingKing(Black)]),
+    );
+
+    let state = GameStateBuilder::new()
+        .placement(Placement::new(White, King, 4))
+        .placement(Placement::new(White, Rook, 7))
+        .placement(Placement::new(Black, King, 60))
+        .to_move(Black)
+        .castling_right(White, Kingside, true)
+        .build()
+        .unwrap();
+    assert!(state.white_can_castle_kingside);
+    assert!(state.to_move == Black);
+}
+
+#[test]
+fn builder_editing_test() {
+    let state = GameStateBuilder::starting_position()
+        .move_piece(12, 28)
+        .remove(1)
+        .put(Piece::new(White, Knight), 21)
+        .to_move(Black)
+        .en_passant_square(Some(20))
+        .castling_right(White, Queenside, false)
+        .build()
+        .unwrap();
+
+    assert!(state.squares[12].is_none());
+    assert!(state.squares[1].is_none());
+    assert!(state.squares[28].unwrap().name == Pawn);
+    assert!(state.squares[21].unwrap().name == Knight);
+    assert!(!state.white_can_castle_queenside);
+    assert!(state.white_can_castle_kingside);
+    assert_eq!(Some(20), state.en_passant_square);
+}
+
+#[test]
+fn builder_put_on_occupied_square_test() {
+    let result = GameStateBuilder::starting_position()
+        .put(Piece::new(White, Queen), 12)
+        .build();
+    assert_eq!(result.unwrap_err(), BuilderError::SquareOccupied(12));
+
+    // Only the first failed edit is reported
+    let result = GameStateBuilder::new()
+        .remove(30)
+        .put(Piece::new(White, Queen), 64)
+        .build();
+    assert_eq!(result.unwrap_err(), BuilderError::SquareEmpty(30));
+}
+
+#[test]
+fn builder_clear_test() {
+    let state = GameStateBuilder::starting_position()
+        .clear()
+        .castling_right(White, Kingside, false)
+        .castling_right(White, Queenside, false)
+        .castling_right(Black, Kingside, false)
+        .castling_right(Black, Queenside, false)
+        .put(Piece::new(White, King), 0)
+        .put(Piece::new(Black, King), 63)
+        .build()
+        .unwrap();
+    assert_eq!(2, state.squares.iter().filter(|s| s.is_some()).count());
+}
+
+#[test]
+fn builder_mirror_test() {
+    let state = GameStateBuilder::starting_position()
+        .move_piece(6, 21)
+        .mirror()
+        .build()
+        .unwrap();
+    assert!(state.squares[18].unwrap().name == Knight);
+    assert!(state.squares[1].is_none());
+    assert!(state.squares[3].unwrap().name == King);
+    assert!(state.squares[4].unwrap().name == Queen);
+    assert!(!state.white_can_castle_kingside);
+}
+
+#[test]
+fn builder_flip_test() {
+    let state = GameStateBuilder::starting_position()
+        .move_piece(12, 28)
+        .to_move(Black)
+        .en_passant_square(Some(20))
+        .castling_right(White, Kingside, false)
+        .flip()
+        .build()
+        .unwrap();
+    let piece = state.squares[36].unwrap();
+    assert!(piece.color == Black && piece.name == Pawn);
+    let piece = state.squares[4].unwrap();
+    assert!(piece.color == White && piece.name == King);
+    assert!(state.to_move == White);
+    assert_eq!(Some(44), state.en_passant_square);
+    assert!(!state.black_can_castle_kingside);
+    assert!(state.white_can_castle_kingside);
+}
+
+#[test]
+fn text_renderer_default_matches_to_string_test() {
+    let state = GameState::new();
+    assert_eq!(state.to_string(), TextRenderer::new().render(&state));
+}
+
+#[test]
+fn text_renderer_unicode_with_coordinates_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(Black, Queen, 59),
+    ]);
+    let expected = format!(
+        "{}{}{}{}{}{}{}{}{}",
+        "8 · · · ♛ · · · · \n",
+        "7 · · · · · · · · \n",
+        "6 · · · · · · · · \n",
+        "5 · · · · · · · · \n",
+        "4 · · · · · · · · \n",
+        "3 · · · · · · · · \n",
+        "2 · · · · · · · · \n",
+        "1 · · · · ♔ · · · \n",
+        "  a b c d e f g h \n",
+    );
+    let output = TextRenderer::new()
+        .unicode(true)
+        .coordinates(true)
+        .render(&state);
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn text_renderer_black_perspective_test() {
+    let output = TextRenderer::new()
+        .coordinates(true)
+        .perspective(Black)
+        .render(&GameState::new());
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!("1 R N B K Q B N R ", lines[0]);
+    assert_eq!("8 r n b k q b n r ", lines[7]);
+    assert_eq!("  h g f e d c b a ", lines[8]);
+}
+
+#[test]
+fn text_renderer_ansi_highlights_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(Black, Rook, 60),
+    ]);
+    let output = TextRenderer::new()
+        .ansi_colors(true)
+        .last_move(52, 60)
+        .highlight_check(true)
+        .render(&state);
+
+    // The checked king is drawn on a red square, and both squares of the
+    // last move are highlighted
+    assert!(output.contains("\x1b[48;5;167m\x1b[1;97m K \x1b[0m"));
+    assert!(output.contains("\x1b[48;5;143m\x1b[1;30m r \x1b[0m"));
+    assert!(output.contains("\x1b[48;5;143m\x1b[1;97m   \x1b[0m"));
+}
+
+#[test]
+fn svg_renderer_document_test() {
+    let svg = SvgRenderer::new().render(&GameState::new());
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.ends_with("</svg>\n"));
+    assert_eq!(64, svg.matches("<rect").count());
+    assert_eq!(32 + 16, svg.matches("<text").count());
+    assert!(!svg.contains("<image"));
+}
+
+#[test]
+fn svg_renderer_square_placement_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 0),
+    ]);
+    let svg = SvgRenderer::new()
+        .coordinates(false)
+        .square_size(10)
+        .render(&state);
+    // a1 is the bottom left square from white's side
+    assert!(svg.contains("<text x=\"5\" y=\"75\""));
+
+    let svg = SvgRenderer::new()
+        .coordinates(false)
+        .square_size(10)
+        .perspective(Black)
+        .render(&state);
+    assert!(svg.contains("<text x=\"75\" y=\"5\""));
+}
+
+#[test]
+fn svg_renderer_annotations_test() {
+    let svg = SvgRenderer::new()
+        .coordinates(false)
+        .last_move(12, 28)
+        .highlight(36)
+        .arrow(6, 21)
+        .arrow(1, 18)
+        .render(&GameState::new());
+    assert_eq!(64 + 2 + 1, svg.matches("<rect").count());
+    assert_eq!(2, svg.matches("marker-end=\"url(#arrowhead)\"").count());
+}
+
+#[test]
+fn svg_renderer_ignores_squares_off_the_board_test() {
+    let plain = SvgRenderer::new().render(&GameState::new());
+    let svg = SvgRenderer::new()
+        .last_move(12, 64)
+        .highlight(64)
+        .highlight(usize::MAX)
+        .arrow(6, 100)
+        .arrow(70, 21)
+        .render(&GameState::new());
+    assert_eq!(plain, svg);
+}
+
+#[test]
+fn from_diagram_round_trip_test() {
+    let state = GameState::new();
+    let parsed = GameState::from_diagram(&state.to_string()).unwrap();
+    assert_eq!(state.to_string(), parsed.to_string());
+    assert!(parsed.to_move == White);
+    assert!(!parsed.white_can_castle_kingside);
+}
+
+#[test]
+fn from_diagram_annotation_test() {
+    let state = GameState::from_diagram("
+        . . . . k . . r
+        . . . . . . . .
+        . . . . . . . .
+        . . . . . . . .
+        . . . . . . . .
+        . . . . . . . .
+        . . . . . . . .
+        R . . . K . . .
+        b Qk
+    ").unwrap();
+
+    assert!(state.to_move == Black);
+    assert!(state.white_can_castle_queenside);
+    assert!(state.black_can_castle_kingside);
+    assert!(!state.white_can_castle_kingside);
+    assert!(!state.black_can_castle_queenside);
+    assert!(Castle { direction: Kingside }.is_legal(&state));
+    let piece = state.squares[63].unwrap();
+    assert!(piece.color == Black && piece.name == Rook);
+}
+
+#[test]
+fn from_diagram_errors_test() {
+    let rank = ". . . . . . . .\n";
+    let seven_ranks = rank.repeat(7);
+
+    let result = GameState::from_diagram(&seven_ranks);
+    assert_eq!(result.unwrap_err(), DiagramError::WrongRankCount(7));
+
+    let ten_ranks = rank.repeat(10);
+    let result = GameState::from_diagram(&ten_ranks);
+    assert_eq!(result.unwrap_err(), DiagramError::WrongRankCount(10));
+
+    let diagram = format!("{}. . . . . . .\n", seven_ranks);
+    let result = GameState::from_diagram(&diagram);
+    assert_eq!(result.unwrap_err(), DiagramError::WrongFileCount(1, 7));
+
+    let diagram = format!("{}. . . x . . . .\n", seven_ranks);
+    let result = GameState::from_diagram(&diagram);
+    assert_eq!(result.unwrap_err(), DiagramError::UnknownSymbol('x'));
+
+    let diagram = format!("{}{}white\n", seven_ranks, rank);
+    let result = GameState::from_diagram(&diagram);
+    assert_eq!(
+        result.unwrap_err(),
+        DiagramError::InvalidAnnotation(String::from("white")),
+    );
+}
+
+#[test]
+fn search_finds_mate_in_one_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 6),
+        Placement::new(White, Rook, 0),
+        Placement::new(Black, King, 63),
+        Placement::new(Black, Pawn, 54),
+        Placement::new(Black, Pawn, 55),
+    ]);
+    let result = best_move(&state, 2);
+    assert_eq!(Some(ChessMove::Move(Move { from: 0, to: 56 })), result.best_move);
+    assert_eq!(MATE_SCORE - 1, result.score);
+    assert_eq!(Some(1), result.mate_in());
+    assert_eq!(vec![String::from("Ra8")], result.principal_variation);
+}
+
+#[test]
+fn search_finds_mate_in_two_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 0),
+        Placement::new(White, Rook, 9),
+        Placement::new(Black, King, 61),
+    ]);
+    let result = best_move(&state, 3);
+    assert_eq!(Some(2), result.mate_in());
+    assert_eq!(3, result.principal_variation.len());
+
+    // The moves of the line are the ones written out, ending in mate
+    let mut position = state;
+    for (action, notation) in result.principal_variation_moves.iter().zip(result.principal_variation.iter()) {
+        assert_eq!(*notation, action.as_algebraic_notation(&position));
+        position = action.apply(&position);
+    }
+    assert_eq!(3, result.principal_variation_moves.len());
+    assert!(is_checkmate(&position));
+}
+
+#[test]
+fn search_captures_hanging_queen_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Knight, 21),
+        Placement::new(Black, Queen, 36),
+        Placement::new(Black, King, 60),
+    ]);
+    let result = Search::new(SearchLimits::new().depth(1))
+        .with_evaluator(Arc::new(MaterialEvaluator))
+        .run(&state);
+    assert_eq!(Some(ChessMove::Capture(Capture { on: 36, with: 21 })), result.best_move);
+    assert_eq!(vec!["Nxe5", "Ke7"], result.principal_variation);
+    assert_eq!(300, result.score);
+}
+
+#[test]
+fn search_reports_being_mated_test() {
+    // Black to move is checkmated
+    let mut state = GameState::with_placements(vec![
+        Placement::new(White, King, 46),
+        Placement::new(White, Queen, 54),
+        Placement::new(Black, King, 63),
+    ]);
+    state.to_move = Black;
+    let result = best_move(&state, 2);
+    assert_eq!(None, result.best_move);
+    assert_eq!(-MATE_SCORE, result.score);
+    assert_eq!(Some(0), result.mate_in());
+    assert_eq!(Some(-3), mate_distance(-(MATE_SCORE - 5)));
+    assert_eq!(None, mate_distance(250));
+}
+
+#[test]
+fn iterative_deepening_reports_each_iteration_test() {
+    let depths = Arc::new(Mutex::new(vec![]));
+    let reported = Arc::clone(&depths);
+
+    let result = Search::new(SearchLimits::new().depth(3))
+        .on_iteration(move |info| {
+            assert!(!info.principal_variation.is_empty());
+            assert!(info.nodes > 0);
+            reported.lock().unwrap().push(info.depth);
+        })
+        .run(&GameState::new());
+
+    assert_eq!(vec![1, 2, 3], *depths.lock().unwrap());
+    assert_eq!(3, result.depth);
+    assert!(result.best_move.is_some());
+}
+
+#[test]
+fn iterative_deepening_stops_at_mate_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 6),
+        Placement::new(White, Rook, 0),
+        Placement::new(Black, King, 63),
+        Placement::new(Black, Pawn, 54),
+        Placement::new(Black, Pawn, 55),
+    ]);
+    let result = Search::new(SearchLimits::new()).run(&state);
+    assert_eq!(Some(1), result.mate_in());
+    assert_eq!(1, result.depth);
+}
+
+#[test]
+fn search_node_limit_test() {
+    let result = Search::new(SearchLimits::new().nodes(500)).run(&GameState::new());
+    assert!(result.nodes <= 500);
+    assert!(result.best_move.is_some());
+}
+
+#[test]
+fn search_move_time_test() {
+    let started = Instant::now();
+    let limits = SearchLimits::new().move_time(Duration::from_millis(200));
+    let result = Search::new(limits).run(&GameState::new());
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(result.best_move.is_some());
+}
+
+#[test]
+fn search_stop_flag_test() {
+    // A search stopped before it begins still returns a legal action
+    let stop = Arc::new(AtomicBool::new(true));
+    let state = GameState::new();
+    let result = Search::new(SearchLimits::new())
+        .with_stop_flag(Arc::clone(&stop))
+        .run(&state);
+    assert!(result.best_move.unwrap().is_legal(&state));
+
+    // An unlimited search can be stopped from another thread
+    let mut search = Search::new(SearchLimits::new());
+    let stop = search.stop_flag();
+    let started = Instant::now();
+    let handle = std::thread::spawn(move || search.run(&GameState::new()));
+    std::thread::sleep(Duration::from_millis(100));
+    stop.store(true, Ordering::Relaxed);
+    let result = handle.join().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(result.best_move.is_some());
+}
+
+#[test]
+fn search_time_budget_test() {
+    let limits = SearchLimits::new().move_time(Duration::from_millis(500));
+    let budget = limits.time_budget(White).unwrap();
+    assert_eq!(budget.soft, Duration::from_millis(500));
+    assert_eq!(budget.hard, Duration::from_millis(500));
+
+    assert_eq!(None, SearchLimits::new().depth(4).time_budget(White));
+
+    let limits = SearchLimits::new().clock(
+        Duration::from_secs(60),
+        Duration::from_secs(10),
+        Duration::from_secs(2),
+        Duration::from_secs(0),
+    );
+    assert_eq!(limits.time_budget(White), Some(TimeBudget {
+        soft: Duration::from_millis(3500),
+        hard: Duration::from_millis(10500),
+    }));
+
+    // With one move to go, never plan to use more than the clock holds
+    let budget = limits.moves_to_go(1).time_budget(Black).unwrap();
+    assert_eq!(budget.hard, Duration::from_millis(9970));
+    assert_eq!(budget.soft, Duration::from_millis(9970));
+}
+
+#[test]
+fn hash_of_transposed_positions_test() {
+    let start = GameState::new();
+    let knights_first = Move { from: 6, to: 21 }.apply(&start);
+    let knights_first = Move { from: 62, to: 45 }.apply(&knights_first);
+    let knights_first = Move { from: 1, to: 18 }.apply(&knights_first);
+
+    let other_order = Move { from: 1, to: 18 }.apply(&start);
+    let other_order = Move { from: 62, to: 45 }.apply(&other_order);
+    let other_order = Move { from: 6, to: 21 }.apply(&other_order);
+
+    assert_eq!(knights_first.hash(), other_order.hash());
+    assert!(knights_first.hash() != start.hash());
+}
+
+#[test]
+fn hash_includes_non_placement_state_test() {
+    let state = GameState::new();
+    let mut other = state;
+    other.to_move = Black;
+    assert!(state.hash() != other.hash());
+
+    let mut other = state;
+    other.white_can_castle_kingside = false;
+    assert!(state.hash() != other.hash());
+
+    let mut other = state;
+    other.en_passant_square = Some(20);
+    assert!(state.hash() != other.hash());
+}
+
+#[test]
+fn transposition_table_store_and_probe_test() {
+    let table = TranspositionTable::new(1);
+    let action = ChessMove::Move(Move { from: 12, to: 28 });
+    table.store(42, 3, Bound::Exact, 15, Some(action));
+
+    let entry = table.probe(42).unwrap();
+    assert_eq!(3, entry.depth);
+    assert_eq!(Bound::Exact, entry.bound);
+    assert_eq!(15, entry.score);
+    assert_eq!(Some(action), entry.best_move);
+    assert!(table.probe(43).is_none());
+
+    // A later store without a best move keeps the one already known
+    table.store(42, 4, Bound::Upper, -20, None);
+    let entry = table.probe(42).unwrap();
+    assert_eq!(4, entry.depth);
+    assert_eq!(Some(action), entry.best_move);
+
+    table.clear();
+    assert!(table.probe(42).is_none());
+}
+
+#[test]
+fn transposition_table_replacement_test() {
+    let table = TranspositionTable::new(1);
+
+    // Keys a whole number of tables apart share a bucket
+    let buckets = table.capacity() as u64 / 2;
+    let (a, b, c, d) = (7, 7 + buckets, 7 + 2 * buckets, 7 + 3 * buckets);
+
+    table.store(a, 5, Bound::Exact, 0, None);
+    table.store(b, 1, Bound::Exact, 0, None);
+    assert!(table.probe(a).is_some() && table.probe(b).is_some());
+
+    // The deepest entry survives, the shallower one is replaced
+    table.store(c, 2, Bound::Exact, 0, None);
+    assert!(table.probe(a).is_some() && table.probe(c).is_some());
+    assert!(table.probe(b).is_none());
+
+    // Deep entries from earlier searches give way to new ones
+    table.new_search();
+    table.store(d, 1, Bound::Exact, 0, None);
+    assert!(table.probe(d).is_some() && table.probe(a).is_some());
+    assert!(table.probe(c).is_none());
+}
+
+#[test]
+fn mate_scores_are_stored_relative_to_position_test() {
+    // Mate in 3 plies from the root, seen from a node 2 plies deep,
+    // is mate in 1 ply from that node
+    let score = MATE_SCORE - 3;
+    assert_eq!(MATE_SCORE - 1, score_to_table(score, 2));
+    assert_eq!(score, score_from_table(score_to_table(score, 2), 2));
+    assert_eq!(-(MATE_SCORE - 1), score_to_table(-score, 2));
+    assert_eq!(35, score_to_table(35, 7));
+}
+
+#[test]
+fn search_with_shared_transposition_table_test() {
+    let table = Arc::new(TranspositionTable::new(1));
+    let state = GameState::new();
+
+    let first = Search::new(SearchLimits::new().depth(3))
+        .with_transposition_table(Arc::clone(&table))
+        .run(&state);
+    let second = Search::new(SearchLimits::new().depth(3))
+        .with_transposition_table(Arc::clone(&table))
+        .run(&state);
+
+    assert_eq!(first.score, second.score);
+    assert_eq!(first.best_move, second.best_move);
+    assert!(second.nodes < first.nodes);
+    assert_eq!(3, second.principal_variation.len());
+}
+
+#[test]
+fn search_default_transposition_table_is_small_test() {
+    let search = Search::new(SearchLimits::new().depth(1));
+    let small = TranspositionTable::new(SEARCH_TABLE_MEGABYTES);
+    assert_eq!(small.capacity(), search.transposition_table().capacity());
+    assert!(small.capacity() < TranspositionTable::default().capacity());
+}
+
+#[test]
+fn see_undefended_capture_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 3),
+        Placement::new(Black, Knight, 35),
+        Placement::new(Black, King, 60),
+    ]);
+    assert_eq!(300, see(&state, &Capture { on: 35, with: 3 }));
+}
+
+#[test]
+fn see_defended_capture_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Queen, 27),
+        Placement::new(White, Pawn, 28),
+        Placement::new(Black, Pawn, 35),
+        Placement::new(Black, Pawn, 44),
+        Placement::new(Black, King, 60),
+    ]);
+    // Taking with the queen loses it for two pawns
+    assert_eq!(-700, see(&state, &Capture { on: 35, with: 27 }));
+    // Taking with the pawn wins a pawn, since the queen has the last word
+    assert_eq!(100, see(&state, &Capture { on: 35, with: 28 }));
+}
+
+#[test]
+fn see_x_ray_test() {
+    // The rook behind the first attacker joins in once the first moves
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 6),
+        Placement::new(White, Rook, 3),
+        Placement::new(White, Rook, 11),
+        Placement::new(Black, Pawn, 35),
+        Placement::new(Black, Rook, 59),
+        Placement::new(Black, King, 62),
+    ]);
+    assert_eq!(100, see(&state, &Capture { on: 35, with: 11 }));
+
+    // Without the second rook the exchange loses a rook for a pawn
+    let mut state = state;
+    state.squares[3] = None;
+    assert_eq!(-400, see(&state, &Capture { on: 35, with: 11 }));
+}
+
+#[test]
+fn see_pawns_capture_forwards_only_test() {
+    // The black pawn behind the target can't recapture
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Knight, 20),
+        Placement::new(Black, Bishop, 35),
+        Placement::new(Black, Pawn, 26),
+        Placement::new(Black, King, 60),
+    ]);
+    assert_eq!(300, see(&state, &Capture { on: 35, with: 20 }));
+}
+
+#[test]
+fn quiescence_avoids_defended_pawn_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Queen, 27),
+        Placement::new(Black, Pawn, 35),
+        Placement::new(Black, Pawn, 44),
+        Placement::new(Black, King, 60),
+    ]);
+    let result = best_move(&state, 1);
+    assert!(result.best_move != Some(ChessMove::Capture(Capture { on: 35, with: 27 })));
+    assert!(result.score >= 700);
+}
+
+#[cfg(test)]
+fn search_nodes(state: &GameState, depth: usize, features: SearchFeatures) -> u64 {
+    Search::new(SearchLimits::new().depth(depth))
+        .with_features(features)
+        .with_transposition_table(Arc::new(TranspositionTable::new(1)))
+        .run(state)
+        .nodes
+}
+
+#[test]
+fn selective_search_features_can_be_disabled_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 0),
+        Placement::new(White, Rook, 9),
+        Placement::new(Black, King, 61),
+    ]);
+    let all = SearchFeatures::default();
+    let variations = [
+        SearchFeatures::none(),
+        SearchFeatures { null_move: false, ..all },
+        SearchFeatures { late_move_reductions: false, ..all },
+        SearchFeatures { futility_pruning: false, ..all },
+        SearchFeatures { reverse_futility_pruning: false, ..all },
+        SearchFeatures { check_extensions: false, ..all },
+        SearchFeatures { killer_moves: false, ..all },
+        SearchFeatures { history_heuristic: false, ..all },
+        SearchFeatures { countermoves: false, ..all },
+        all,
+    ];
+    for features in variations.iter() {
+        let result = Search::new(SearchLimits::new().depth(3))
+            .with_features(*features)
+            .run(&state);
+        assert_eq!(Some(2), result.mate_in(), "{:?}", features);
+    }
+}
+
+#[test]
+fn selective_search_reduces_nodes_test() {
+    let state = GameState::new();
+    let plain = search_nodes(&state, 4, SearchFeatures::none());
+    let selective = search_nodes(&state, 4, SearchFeatures::default());
+    assert!(selective < plain);
+}
+
+#[test]
+fn null_move_is_skipped_in_pawn_endings_test() {
+    let null_move_only = SearchFeatures { null_move: true, ..SearchFeatures::none() };
+
+    // With only kings and pawns, null-move pruning never triggers
+    let pawn_ending = GameState::with_placements(vec![
+        Placement::new(White, King, 20),
+        Placement::new(White, Pawn, 27),
+        Placement::new(Black, King, 44),
+        Placement::new(Black, Pawn, 35),
+        Placement::new(Black, Pawn, 49),
+    ]);
+    assert_eq!(
+        search_nodes(&pawn_ending, 4, SearchFeatures::none()),
+        search_nodes(&pawn_ending, 4, null_move_only),
+    );
+
+    // With a piece on the board it prunes
+    let mut with_knight = pawn_ending;
+    with_knight.squares[0] = Some(Piece::new(White, Knight));
+    assert!(
+        search_nodes(&with_knight, 5, null_move_only)
+            < search_nodes(&with_knight, 5, SearchFeatures::none())
+    );
+}
+
+#[test]
+fn classical_evaluation_of_starting_position_test() {
+    let evaluator = ClassicalEvaluator::default();
+    let mut state = GameState::new();
+    assert_eq!(0, evaluator.evaluate(&state));
+    state.to_move = Black;
+    assert_eq!(0, evaluator.evaluate(&state));
+    assert_eq!(24, game_phase(&state));
+}
+
+#[test]
+fn classical_evaluation_is_color_symmetric_test() {
+    let evaluator = ClassicalEvaluator::default();
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 6),
+        Placement::new(White, Pawn, 13),
+        Placement::new(White, Pawn, 14),
+        Placement::new(White, Knight, 21),
+        Placement::new(White, Bishop, 26),
+        Placement::new(White, Pawn, 28),
+        Placement::new(Black, King, 60),
+        Placement::new(Black, Rook, 56),
+        Placement::new(Black, Pawn, 48),
+        Placement::new(Black, Pawn, 40),
+        Placement::new(Black, Queen, 43),
+    ]);
+    let flipped = *GameStateBuilder::from_state(state).flip().state();
+    assert_eq!(evaluator.evaluate(&state), evaluator.evaluate(&flipped));
+}
+
+#[test]
+fn classical_evaluation_is_from_side_to_move_test() {
+    let evaluator = ClassicalEvaluator::default();
+    let mut state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 0),
+        Placement::new(Black, King, 60),
+    ]);
+    let white_score = evaluator.evaluate(&state);
+    assert!(white_score > 400);
+    state.to_move = Black;
+    assert_eq!(-white_score, evaluator.evaluate(&state));
+}
+
+#[test]
+fn classical_evaluation_rewards_bishop_pair_test() {
+    let bishops = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Bishop, 2),
+        Placement::new(White, Bishop, 5),
+        Placement::new(Black, King, 60),
+        Placement::new(Black, Bishop, 58),
+        Placement::new(Black, Knight, 62),
+    ]);
+    let weights = EvaluationWeights { bishop_pair: (0, 0), ..EvaluationWeights::default() };
+    let with_pair = ClassicalEvaluator::default().evaluate(&bishops);
+    let without_pair = ClassicalEvaluator::new(weights).evaluate(&bishops);
+    assert!(with_pair > without_pair);
+}
+
+#[test]
+fn classical_evaluation_pawn_structure_test() {
+    let evaluator = ClassicalEvaluator::default();
+    let with_pawns = |white: usize, other: usize, black: usize| {
+        let mut state = GameState::with_placements(vec![
+            Placement::new(White, King, 4),
+            Placement::new(White, Pawn, white),
+            Placement::new(White, Pawn, other),
+            Placement::new(Black, King, 60),
+            Placement::new(Black, Pawn, black),
+        ]);
+        state.to_move = White;
+        evaluator.evaluate(&state)
+    };
+
+    // Connected pawns are better than doubled, isolated ones
+    assert!(with_pawns(11, 12, 55) > with_pawns(11, 19, 55));
+
+    // A passed pawn is better than one that is blocked
+    assert!(with_pawns(35, 8, 48) > with_pawns(35, 8, 51));
+}
+
+#[test]
+fn classical_evaluation_king_safety_test() {
+    let evaluator = ClassicalEvaluator::default();
+    let mut sheltered = GameState::new();
+    let mut exposed = GameState::new();
+    // Push the pawns in front of the castled king up the board
+    sheltered.squares[4] = None;
+    sheltered.squares[7] = None;
+    sheltered.squares[6] = Some(Piece::new(White, King));
+    sheltered.squares[5] = Some(Piece::new(White, Rook));
+    exposed.squares = sheltered.squares;
+    for square in [13, 14, 15].iter() {
+        exposed.squares[*square] = None;
+        exposed.squares[*square + 16] = Some(Piece::new(White, Pawn));
+    }
+    assert!(evaluator.evaluate(&sheltered) > evaluator.evaluate(&exposed));
+}
+
+#[test]
+fn material_evaluator_test() {
+    let mut state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Knight, 0),
+        Placement::new(Black, King, 60),
+        Placement::new(Black, Pawn, 50),
+    ]);
+    assert_eq!(200, MaterialEvaluator.evaluate(&state));
+    state.to_move = Black;
+    assert_eq!(-200, MaterialEvaluator.evaluate(&state));
+}
+
+// Prefers positions with a black rook on a1, whatever else happens
+#[cfg(test)]
+struct RookOnA1;
+
+#[cfg(test)]
+impl Evaluator for RookOnA1 {
+    fn evaluate(&self, state: &GameState) -> i32 {
+        let score = match state.squares[0] {
+            Some(piece) if piece.color == Black && piece.name == Rook => 500,
+            _ => 0,
+        };
+        match state.to_move {
+            White => -score,
+            Black => score,
+        }
+    }
+}
+
+#[test]
+fn search_uses_pluggable_evaluator_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 7),
+        Placement::new(White, Pawn, 15),
+        Placement::new(Black, King, 39),
+        Placement::new(Black, Rook, 56),
+        Placement::new(Black, Queen, 59),
+        Placement::new(Black, Pawn, 55),
+    ]);
+    let mut state = state;
+    state.to_move = Black;
+    let result = Search::new(SearchLimits::new().depth(1))
+        .with_evaluator(Arc::new(RookOnA1))
+        .run(&state);
+    assert_eq!("Ra1", result.principal_variation[0]);
+}
+
+#[test]
+fn from_fen_round_trip_test() {
+    let state = GameState::new();
+    let parsed = GameState::from_fen(&fen_notation(&state)).unwrap();
+    assert_eq!(fen_notation(&state), fen_notation(&parsed));
+
+    let fen = "r3k2r/pp3ppp/8/3pP3/8/8/PPP2PPP/R3K2R w Kq d6 0 12";
+    let parsed = GameState::from_fen(fen).unwrap();
+    assert_eq!(Some(43), parsed.en_passant_square);
+    assert!(parsed.white_can_castle_kingside);
+    assert!(!parsed.white_can_castle_queenside);
+    assert!(parsed.black_can_castle_queenside);
+    assert_eq!("r3k2r/pp3ppp/8/3pP3/8/8/PPP2PPP/R3K2R w Kq d6 0 0", fen_notation(&parsed));
+
+    // The clocks are optional
+    assert!(GameState::from_fen("8/8/8/8/8/8/8/K6k b - -").is_ok());
+}
+
+#[test]
+fn from_fen_errors_test() {
+    assert_eq!(GameState::from_fen("8/8/8 w - -").unwrap_err(), FenError::WrongRankCount(3));
+    assert_eq!(GameState::from_fen("8/8/8/8/8/8/8/8").unwrap_err(), FenError::WrongFieldCount(1));
+    assert_eq!(
+        GameState::from_fen("8/8/8/8/8/8/8/K5k w - -").unwrap_err(),
+        FenError::WrongFileCount(1, 7),
+    );
+    assert_eq!(
+        GameState::from_fen("8/8/8/8/8/8/8/K6x w - -").unwrap_err(),
+        FenError::UnknownSymbol('x'),
+    );
+    assert_eq!(
+        GameState::from_fen("8/8/8/8/8/8/8/K6k x - -").unwrap_err(),
+        FenError::InvalidSideToMove("x".to_string()),
+    );
+    assert_eq!(
+        GameState::from_fen("8/8/8/8/8/8/8/K6k w KX -").unwrap_err(),
+        FenError::InvalidCastlingRights("KX".to_string()),
+    );
+    assert_eq!(
+        GameState::from_fen("8/8/8/8/8/8/8/K6k w - z9").unwrap_err(),
+        FenError::InvalidEnPassantSquare("z9".to_string()),
+    );
+    // The square must be behind a pawn the other side just moved
+    assert_eq!(
+        GameState::from_fen("4k3/8/8/8/8/8/8/4K3 w - a1 0 1").unwrap_err(),
+        FenError::InvalidEnPassantSquare("a1".to_string()),
+    );
+    assert_eq!(
+        GameState::from_fen("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1").unwrap_err(),
+        FenError::InvalidEnPassantSquare("e3".to_string()),
+    );
+    assert_eq!(
+        GameState::from_fen("4k3/8/8/4p3/8/8/8/4K3 b - e6 0 1").unwrap_err(),
+        FenError::InvalidEnPassantSquare("e6".to_string()),
+    );
+    assert!(GameState::from_fen("4k3/8/8/4p3/8/8/8/4K3 w - e6 0 1").is_ok());
+    assert!(GameState::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").is_ok());
+    assert_eq!(
+        GameState::from_fen("8/8/8/8/8/8/8/K6k w - - x 1").unwrap_err(),
+        FenError::InvalidClock("x".to_string()),
+    );
+}
+
+#[test]
+fn evaluation_weights_text_round_trip_test() {
+    let mut weights = EvaluationWeights { bishop_pair: (11, 22), ..EvaluationWeights::default() };
+    weights.middlegame_tables[1][0] = -99;
+    let parsed = EvaluationWeights::from_text(&weights.to_text()).unwrap();
+    assert_eq!(weights, parsed);
+
+    // Missing terms keep their defaults
+    let partial = EvaluationWeights::from_text("# tuned\n\nbishop_pair 1 2\n").unwrap();
+    assert_eq!((1, 2), partial.bishop_pair);
+    assert_eq!(EvaluationWeights::default().mobility, partial.mobility);
+}
+
+#[test]
+fn evaluation_weights_text_errors_test() {
+    match EvaluationWeights::from_text("tempo 10") {
+        Err(WeightsError::UnknownTerm(term)) => assert_eq!("tempo", term),
+        other => panic!("{:?}", other),
+    }
+    match EvaluationWeights::from_text("bishop_pair 10") {
+        Err(WeightsError::WrongValueCount(term, 1)) => assert_eq!("bishop_pair", term),
+        other => panic!("{:?}", other),
+    }
+    match EvaluationWeights::from_text("bishop_pair 10 ten") {
+        Err(WeightsError::InvalidValue(value)) => assert_eq!("ten", value),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn evaluation_weights_parameters_test() {
+    let mut weights = EvaluationWeights::default();
+    let mut parameters = weights.parameters();
+    assert_eq!(6 + 6 + 6 * 64 * 2 + 8 + 2 + 2 + 16 + 2 + 4 + 2, parameters.len());
+
+    let last = parameters.len() - 1;
+    parameters[last] = 77;
+    weights.set_parameters(&parameters);
+    assert_eq!((30, 77), weights.bishop_pair);
+    assert_eq!(parameters, weights.parameters());
+}
+
+#[test]
+fn classical_evaluator_from_file_test() {
+    let path = std::env::temp_dir().join(format!("weights-{}.txt", std::process::id()));
+    let mut weights = EvaluationWeights::default();
+    weights.middlegame_values[4] = 1200;
+    weights.save(&path).unwrap();
+    let evaluator = ClassicalEvaluator::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(1200, evaluator.weights().middlegame_values[4]);
+
+    match ClassicalEvaluator::from_file(&path) {
+        Err(WeightsError::Io(_)) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn read_labelled_positions_test() {
+    let text = "
+        # A comment
+        rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 [0.5]
+        8/8/8/8/8/8/8/K6k w - - c9 \"1-0\";
+        8/8/8/8/8/8/8/K6k b - - 0-1
+        8/8/8/8/8/8/8/K6k b - - 0 1 1/2-1/2
+    ";
+    let positions = read_labelled_positions(text).unwrap();
+    let results: Vec<f64> = positions.iter().map(|position| position.result).collect();
+    assert_eq!(vec![0.5, 1.0, 0.0, 0.5], results);
+    assert_eq!(Black, positions[2].state.to_move);
+
+    match read_labelled_positions("8/8/8/8/8/8/8/K6k w - - 2-0") {
+        Err(TunerError::InvalidResult(1, result)) => assert_eq!("2-0", result),
+        other => panic!("{:?}", other),
+    }
+    match read_labelled_positions("\n8/8/8/8/8/8/8/K6k w - -") {
+        Err(TunerError::MissingResult(2)) => (),
+        other => panic!("{:?}", other),
+    }
+    match read_labelled_positions("8/8/8 w - - 1-0") {
+        Err(TunerError::InvalidFen(1, FenError::WrongRankCount(3))) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn sigmoid_test() {
+    assert_eq!(0.5, sigmoid(0, 1.0));
+    assert!((sigmoid(400, 1.0) - 10.0 / 11.0).abs() < 1e-9);
+    assert!((sigmoid(-400, 1.0) - 1.0 / 11.0).abs() < 1e-9);
+}
+
+#[test]
+fn tuner_reduces_error_test() {
+    // Positions where white's extra knight only drew
+    let positions = read_labelled_positions("
+        8/8/4k3/8/8/2N5/8/4K3 w - - 1/2-1/2
+        8/8/8/3k4/8/8/1N6/6K1 b - - 1/2-1/2
+        4k3/8/8/8/8/5N2/8/K7 w - - 1/2-1/2
+    ").unwrap();
+    let tuner = Tuner::new(positions).step(20);
+    let weights = EvaluationWeights::default();
+
+    let before = tuner.error(&weights);
+    let tuned = tuner.tune(&weights, 1);
+    assert!(tuner.error(&tuned) < before);
+    assert!(tuned.endgame_values[1] < weights.endgame_values[1]);
+}
+
+#[test]
+fn nnue_feature_index_test() {
+    let white_pawn = Piece::new(White, Pawn);
+    let black_pawn = Piece::new(Black, Pawn);
+    assert_eq!(768 * 4 + 12, feature_index(White, 4, &white_pawn, 12));
+    assert_eq!(768 * 4 + 64 * 6 + 12, feature_index(White, 4, &black_pawn, 12));
+    // Black sees the board upside down, with its own pieces first
+    assert_eq!(768 * 4 + 12, feature_index(Black, 60, &black_pawn, 52));
+    assert_eq!(INPUT_SIZE - 1, feature_index(White, 63, &Piece::new(Black, King), 63));
+}
+
+#[test]
+fn nnue_network_bytes_round_trip_test() {
+    let network = Network::random(4, 7);
+    let bytes = network.to_bytes();
+    assert_eq!(12 + 2 * 4 + 2 * INPUT_SIZE * 4 + 2 * 4 + 4, bytes.len());
+    assert_eq!(network, Network::from_bytes(&bytes).unwrap());
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert!(matches!(Network::from_bytes(&bad_magic), Err(NetworkError::BadMagic)));
+
+    let mut bad_version = bytes.clone();
+    bad_version[4] = 9;
+    assert!(matches!(Network::from_bytes(&bad_version), Err(NetworkError::UnsupportedVersion(9))));
+
+    let truncated = &bytes[..bytes.len() - 1];
+    assert!(matches!(Network::from_bytes(truncated), Err(NetworkError::WrongLength(_, _))));
+
+    assert!(matches!(Network::new(4, vec![0; 3], vec![], vec![], 0), Err(NetworkError::WrongLength(4, 3))));
+}
+
+#[test]
+fn nnue_output_does_not_overflow_test() {
+    let state = GameState::new();
+    let saturated = |output_weight: i8, output_bias: i32| {
+        let network = Network::new(
+            2,
+            vec![ACTIVATION_LIMIT as i16; 2],
+            vec![0; INPUT_SIZE * 2],
+            vec![output_weight; 4],
+            output_bias,
+        ).unwrap();
+        NnueEvaluator::new(network).evaluate(&state)
+    };
+    assert_eq!(30_000, saturated(127, i32::MAX - 100_000));
+    assert_eq!(-30_000, saturated(-128, i32::MIN + 100_000));
+    // 4 * 127 * 127 / (127 * 64) * 400 centipawns
+    assert_eq!(3175, saturated(127, 0));
+}
+
+#[test]
+fn nnue_evaluator_from_file_test() {
+    let path = std::env::temp_dir().join(format!("network-{}.nnue", std::process::id()));
+    let network = Network::random(2, 1);
+    network.save(&path).unwrap();
+    let evaluator = NnueEvaluator::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(&network, evaluator.network());
+}
+
+#[test]
+fn nnue_evaluation_is_color_symmetric_test() {
+    let evaluator = NnueEvaluator::new(Network::random(8, 3));
+    let state = GameState::from_fen("r3k2r/pp3ppp/2n5/3pP3/1b6/5N2/PPP2PPP/R1BQK2R w KQkq d6 0 1").unwrap();
+    let flipped = *GameStateBuilder::from_state(state).flip().state();
+    assert_eq!(evaluator.evaluate(&state), evaluator.evaluate(&flipped));
+}
+
+#[test]
+fn nnue_incremental_updates_match_refresh_test() {
+    let evaluator = NnueEvaluator::new(Network::random(8, 11));
+    let mut tracker = evaluator.tracker().unwrap();
+
+    // Castling, en passant, a capture, king moves and a promotion
+    let mut state = GameState::from_fen("4k2r/1P4p1/8/3pP3/8/8/8/R3K3 w Qk d6 0 1").unwrap();
+    tracker.reset(&state);
+    let mut history = vec![state];
+    let line = ["exd6", "O-O", "O-O-O", "Rf2", "Rd2", "Rxd2", "Kxd2", "Kh7", "b8Q"];
+    for notation in line.iter() {
+        let action = legal_chess_moves(&state)
+            .into_iter()
+            .find(|action| action.as_algebraic_notation(&state) == *notation)
+            .unwrap_or_else(|| panic!("{} isn't legal in {:?}", notation, state));
+        let next_state = action.apply(&state);
+        tracker.make(&state, &next_state);
+        state = next_state;
+        assert_eq!(evaluator.evaluate(&state), tracker.evaluate(&state), "after {}", notation);
+        history.push(state);
+    }
+
+    history.pop();
+    while let Some(previous) = history.pop() {
+        tracker.unmake();
+        assert_eq!(evaluator.evaluate(&previous), tracker.evaluate(&previous));
+    }
+}
+
+// Hides the tracker of the evaluator it wraps, so that searches have to
+// evaluate every position from scratch
+#[cfg(test)]
+struct Untracked(NnueEvaluator);
+
+#[cfg(test)]
+impl Evaluator for Untracked {
+    fn evaluate(&self, state: &GameState) -> i32 {
+        self.0.evaluate(state)
+    }
+}
+
+#[test]
+fn search_with_nnue_tracker_test() {
+    let network = Network::random(8, 5);
+    let state = GameState::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 1").unwrap();
+
+    let tracked = Search::new(SearchLimits::new().depth(3))
+        .with_evaluator(Arc::new(NnueEvaluator::new(network.clone())))
+        .run(&state);
+    let untracked = Search::new(SearchLimits::new().depth(3))
+        .with_evaluator(Arc::new(Untracked(NnueEvaluator::new(network))))
+        .run(&state);
+
+    assert_eq!(untracked.best_move, tracked.best_move);
+    assert_eq!(untracked.score, tracked.score);
+    assert_eq!(untracked.nodes, tracked.nodes);
+}
+
+#[test]
+fn accumulator_stack_depth_test() {
+    let network = Arc::new(Network::random(2, 9));
+    let mut stack = AccumulatorStack::new(network);
+    let state = GameState::new();
+    stack.reset(&state);
+    let next_state = legal_chess_moves(&state)[0].apply(&state);
+    stack.make(&state, &next_state);
+    assert_eq!(2, stack.depth());
+    stack.unmake();
+    assert_eq!(1, stack.depth());
+}
+
+#[cfg(test)]
+fn assert_send_sync<T: Send + Sync + ?Sized>() {}
+
+#[test]
+fn core_types_are_send_and_sync_test() {
+    assert_send_sync::<GameState>();
+    assert_send_sync::<ChessMove>();
+    assert_send_sync::<Box<dyn Action>>();
+    assert_send_sync::<TranspositionTable>();
+    assert_send_sync::<SearchResult>();
+}
+
+#[test]
+fn transposition_table_shared_between_threads_test() {
+    let table = Arc::new(TranspositionTable::new(1));
+    let handles: Vec<_> = (0..4u64)
+        .map(|thread| {
+            let table = Arc::clone(&table);
+            std::thread::spawn(move || {
+                for key in (thread * 100)..(thread * 100 + 100) {
+                    table.store(key, 1, Bound::Exact, key as i32, None);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    for key in 0..400 {
+        assert_eq!(key as i32, table.probe(key).unwrap().score);
+    }
+}
+
+#[test]
+fn threaded_search_finds_mate_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 0),
+        Placement::new(White, Rook, 9),
+        Placement::new(Black, King, 61),
+    ]);
+    // However early the mate is seen, the line runs all the way to it
+    for threads in [1, 4].iter() {
+        for depth in [3, 5].iter() {
+            let result = Search::new(SearchLimits::new().depth(*depth))
+                .with_threads(*threads)
+                .run(&state);
+            assert_eq!(Some(2), result.mate_in());
+            assert_eq!(3, result.principal_variation.len());
+        }
+    }
+}
+
+#[test]
+fn threaded_search_stops_on_time_test() {
+    let state = GameState::new();
+    let started = Instant::now();
+    let result = Search::new(SearchLimits::new().move_time(Duration::from_millis(200)))
+        .with_threads(3)
+        .run(&state);
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(result.best_move.is_some());
+    assert!(result.depth >= 1);
+}
+
+#[test]
+fn single_threaded_search_is_deterministic_test() {
+    let state = GameState::new();
+    let search = || {
+        Search::new(SearchLimits::new().depth(3))
+            .with_transposition_table(Arc::new(TranspositionTable::new(1)))
+            .with_threads(1)
+            .run(&state)
+    };
+    let (first, second) = (search(), search());
+    assert_eq!(first.nodes, second.nodes);
+    assert_eq!(first.principal_variation, second.principal_variation);
+}
+
+#[test]
+fn multi_pv_returns_ranked_distinct_lines_test() {
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 4),
+        Placement::new(White, Rook, 0),
+        Placement::new(White, Rook, 9),
+        Placement::new(Black, King, 61),
+    ]);
+    let result = Search::new(SearchLimits::new().depth(3))
+        .with_multi_pv(3)
+        .run(&state);
+
+    assert_eq!(3, result.lines.len());
+    assert_eq!(Some(2), result.lines[0].mate_in());
+    assert_eq!(result.principal_variation, result.lines[0].principal_variation);
+    assert_eq!(result.best_move, Some(result.lines[0].first_move));
+    for pair in result.lines.windows(2) {
+        assert!(pair[0].score >= pair[1].score);
+        assert!(pair[0].first_move != pair[1].first_move);
+    }
+    for line in result.lines.iter() {
+        assert_eq!(3, line.depth);
+        assert_eq!(line.first_move.as_algebraic_notation(&state), line.principal_variation[0]);
+    }
+}
+
+#[test]
+fn multi_pv_is_limited_by_legal_moves_test() {
+    // Only Kb1, axb3, a3 and a4 are legal
+    let state = GameState::with_placements(vec![
+        Placement::new(White, King, 0),
+        Placement::new(White, Pawn, 8),
+        Placement::new(Black, King, 18),
+        Placement::new(Black, Pawn, 17),
+    ]);
+    let result = Search::new(SearchLimits::new().depth(2))
+        .with_multi_pv(5)
+        .run(&state);
+    assert_eq!(4, result.lines.len());
+
+    let single = Search::new(SearchLimits::new().depth(2)).run(&state);
+    assert_eq!(1, single.lines.len());
+}
+
+#[test]
+fn multi_pv_reports_every_line_test() {
+    let reported = Arc::new(Mutex::new(vec![]));
+    let log = Arc::clone(&reported);
+    Search::new(SearchLimits::new().depth(2))
+        .with_multi_pv(3)
+        .on_iteration(move |info| log.lock().unwrap().push((info.depth, info.line)))
+        .run(&GameState::new());
+    assert_eq!(
+        vec![(1, 1), (1, 2), (1, 3), (2, 1), (2, 2), (2, 3)],
+        *reported.lock().unwrap(),
+    );
+}
+
+// Collects what a UCI engine writes, so that tests can read it while the
+// engine still owns the writer
+#[cfg(test)]
+#[derive(Clone)]
+struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl std::io::Write for SharedOutput {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl SharedOutput {
+    fn new() -> SharedOutput {
+        SharedOutput(Arc::new(Mutex::new(vec![])))
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+fn run_uci_script(script: &str) -> String {
+    let output = SharedOutput::new();
+    let mut engine = UciEngine::new(output.clone());
+    engine.run(script.as_bytes());
+    output.text()
+}
+
+#[test]
+fn uci_handshake_test() {
+    let output = run_uci_script("uci\nisready\nquit\n");
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].starts_with("id name "));
+    assert!(lines.contains(&"option name Hash type spin default 16 min 1 max 4096"));
+    assert!(lines.contains(&"option name MultiPV type spin default 1 min 1 max 256"));
+    assert_eq!(&"uciok", &lines[lines.len() - 2]);
+    assert_eq!(&"readyok", lines.last().unwrap());
+}
+
+#[test]
+fn uci_go_depth_test() {
+    let output = run_uci_script("ucinewgame\nposition startpos moves e2e4 e7e5\ngo depth 2\n");
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].starts_with("info depth 1 multipv 1 score cp "));
+    assert!(lines[1].starts_with("info depth 2 multipv 1 score cp "));
+    assert!(lines[1].contains(" pv "));
+    assert!(lines[2].starts_with("bestmove "));
+    assert!(lines[2].contains(" ponder "));
+}
+
+#[test]
+fn uci_finds_mate_test() {
+    let output = run_uci_script("position fen 6k1/8/6K1/8/8/8/8/R7 w - - 0 1\ngo mate 1\n");
+    assert!(output.contains("score mate 1 "));
+    assert_eq!("bestmove a1a8", output.lines().last().unwrap());
+}
+
+#[test]
+fn uci_castling_and_searchmoves_test() {
+    let output = run_uci_script(
+        "position fen r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1 moves e1g1\n\
+         go depth 1 searchmoves e8c8\n",
+    );
+    assert!(!output.contains("illegal"));
+    assert_eq!("bestmove e8c8", output.lines().last().unwrap());
+}
+
+#[test]
+fn uci_reports_illegal_moves_test() {
+    let output = run_uci_script("position startpos moves e2e5\nquit\n");
+    assert_eq!("info string illegal move: e2e5", output.trim());
+}
+
+#[test]
+fn uci_multi_pv_option_test() {
+    let output = run_uci_script("setoption name MultiPV value 3\ngo depth 1\n");
+    assert!(output.contains("info depth 1 multipv 3 "));
+    assert!(!output.contains("multipv 4"));
+}
+
+#[test]
+fn uci_infinite_search_waits_for_stop_test() {
+    let output = SharedOutput::new();
+    let mut engine = UciEngine::new(output.clone());
+    engine.handle("position startpos");
+    engine.handle("go infinite");
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!output.text().contains("bestmove"));
+    engine.handle("stop");
+    assert!(output.text().lines().last().unwrap().starts_with("bestmove "));
+}
+
+#[test]
+fn uci_ponderhit_starts_the_clock_test() {
+    let output = SharedOutput::new();
+    let mut engine = UciEngine::new(output.clone());
+    engine.handle("go ponder movetime 50");
+    std::thread::sleep(Duration::from_millis(150));
+    assert!(!output.text().contains("bestmove"));
+
+    let started = Instant::now();
+    engine.handle("ponderhit");
+    engine.run("".as_bytes());
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(output.text().lines().last().unwrap().starts_with("bestmove "));
+}
+
+#[cfg(test)]
+fn run_cecp_script(script: &str) -> String {
+    let output = SharedOutput::new();
+    let mut engine = CecpEngine::new(output.clone());
+    engine.run(script.as_bytes());
+    output.text()
+}
+
+#[test]
+fn cecp_handshake_test() {
+    let output = run_cecp_script("xboard\nprotover 2\nping 7\n");
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].starts_with("feature myname="));
+    assert!(lines[0].contains(" usermove=1 "));
+    assert!(lines[0].contains(" setboard=1 "));
+    assert!(lines[0].ends_with(" done=1"));
+    assert_eq!("pong 7", lines[1]);
+}
+
+#[test]
+fn cecp_replies_to_user_moves_test() {
+    let output = run_cecp_script("new\nsd 1\nusermove e2e4\n");
+    assert!(output.trim().starts_with("move "));
+}
+
+#[test]
+fn cecp_force_mode_test() {
+    let output = run_cecp_script("new\nforce\nusermove e2e4\ne7e5\nping 1\n");
+    assert_eq!("pong 1", output.trim());
+}
+
+#[test]
+fn cecp_go_announces_mate_test() {
+    let output = run_cecp_script("new\nforce\nf2f3\ne7e5\ng2g4\nsd 2\ngo\n");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(vec!["move d8h4", "0-1 {Black mates}"], lines);
+}
+
+#[test]
+fn cecp_undo_and_remove_test() {
+    let output = run_cecp_script(
+        "new\nforce\ne2e4\ne7e5\nremove\nusermove e2e5\nusermove e2e4\nundo\ne7e5\n",
+    );
+    // After remove the pawn is back on e2, and after undo e7e5 is illegal
+    assert_eq!("Illegal move: e2e5\nIllegal move: e7e5", output.trim());
+}
+
+#[test]
+fn cecp_setboard_test() {
+    let output = run_cecp_script(
+        "new\nforce\nsetboard nonsense\nsetboard 6k1/5ppp/8/8/8/8/8/R6K w - - 0 1\nsd 2\ngo\n",
+    );
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(vec!["tellusererror Illegal position", "move a1a8", "1-0 {White mates}"], lines);
+}
+
+#[test]
+fn cecp_level_sets_the_clocks_test() {
+    // Without a depth or a `time` command, only BASE limits the search
+    let started = Instant::now();
+    let output = run_cecp_script("new\nlevel 40 0:02 0\ne2e4\n");
+    assert!(output.trim().starts_with("move "));
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn cecp_post_shows_thinking_test() {
+    let output = run_cecp_script("new\npost\nsd 2\ne2e4\n");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(3, lines.len());
+    assert!(lines[0].starts_with("1 "));
+    assert!(lines[1].starts_with("2 "));
+    assert!(lines[2].starts_with("move "));
+}
+
+#[test]
+fn uci_move_round_trip_test() {
+    let state = GameState::new();
+    for action in legal_chess_moves(&state) {
+        let text = to_uci(&state, &action);
+        assert_eq!(Ok(action), parse_uci_move(&state, &text));
+    }
+    assert_eq!(ChessMove::Move(Move { from: 12, to: 28 }), parse_uci_move(&state, "e2e4").unwrap());
+}
+
+#[test]
+fn uci_move_castling_test() {
+    let state = GameState::from_fen("r3k2r/8/8/8/8/8/8/4K3 b kq - 0 1").unwrap();
+    let action = ChessMove::Castle(Castle { direction: Queenside });
+    assert_eq!(Ok(action), parse_uci_move(&state, "e8c8"));
+    assert_eq!("e8c8", to_uci(&state, &action));
+    assert_eq!("e8g8", to_uci(&state, &ChessMove::Castle(Castle { direction: Kingside })));
+}
+
+#[test]
+fn uci_move_en_passant_test() {
+    let state = GameState::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+    let action = ChessMove::EnPassant(EnPassant { with: 36 });
+    assert_eq!(Ok(action), parse_uci_move(&state, "e5d6"));
+    assert_eq!("e5d6", to_uci(&state, &action));
+}
+
+#[test]
+fn uci_move_promotion_test() {
+    let state = GameState::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let action = ChessMove::Promotion(Promotion { pawn_becomes: Knight, moving_from: 49, to: 57 });
+    assert_eq!(Ok(action), parse_uci_move(&state, "b7b8n"));
+    assert_eq!("b7b8n", to_uci(&state, &action));
+}
+
+#[test]
+fn uci_move_errors_test() {
+    let state = GameState::new();
+    assert_eq!(Err(UciMoveError::IllegalMove("e2e5".to_string())), parse_uci_move(&state, "e2e5"));
+    assert_eq!(Err(UciMoveError::InvalidSyntax("e2".to_string())), parse_uci_move(&state, "e2"));
+    assert_eq!(Err(UciMoveError::InvalidSyntax("e2e4k".to_string())), parse_uci_move(&state, "e2e4k"));
+    assert_eq!(Err(UciMoveError::InvalidSyntax("e9e4".to_string())), parse_uci_move(&state, "e9e4"));
+}
+
+#[test]
+fn parse_san_test() {
+    let state = GameState::new();
+    assert_eq!(Some(parse_uci_move(&state, "g1f3").unwrap()), parse_san(&state, "Nf3"));
+    assert_eq!(Some(parse_uci_move(&state, "e2e4").unwrap()), parse_san(&state, "e4!"));
+    assert_eq!(None, parse_san(&state, "e5"));
+    assert_eq!(None, parse_san(&state, "Z"));
+
+    let state = GameState::from_fen("r3k2r/8/8/8/8/8/8/4K3 b kq - 0 1").unwrap();
+    assert_eq!(Some(parse_uci_move(&state, "e8g8").unwrap()), parse_san(&state, "O-O"));
+    assert_eq!(Some(parse_uci_move(&state, "e8c8").unwrap()), parse_san(&state, "0-0-0+"));
+
+    // Disambiguation and promotions
+    let state = GameState::from_fen("4k3/1P6/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+    assert_eq!(None, parse_san(&state, "Rd1"));
+    assert_eq!(Some(parse_uci_move(&state, "a1d1").unwrap()), parse_san(&state, "Rad1"));
+    assert_eq!(Some(parse_uci_move(&state, "h1d1").unwrap()), parse_san(&state, "Rhd1"));
+    assert_eq!(Some(parse_uci_move(&state, "b7b8n").unwrap()), parse_san(&state, "b8=N"));
+    assert_eq!(Some(parse_uci_move(&state, "b7b8q").unwrap()), parse_san(&state, "b8Q"));
+}
+
+#[test]
+fn time_control_parse_test() {
+    assert_eq!(
+        Some(TimeControl::Clock {
+            base: Duration::from_secs(60),
+            increment: Duration::from_millis(500),
+            moves: Some(40),
+        }),
+        TimeControl::parse("40/60+0.5"),
+    );
+    assert_eq!(
+        Some(TimeControl::Clock { base: Duration::from_secs(10), increment: Duration::from_secs(0), moves: None }),
+        TimeControl::parse("10"),
+    );
+    assert_eq!(Some(TimeControl::MoveTime(Duration::from_millis(100))), TimeControl::parse("st=0.1"));
+    assert_eq!(Some(TimeControl::Depth(4)), TimeControl::parse("depth=4"));
+    assert_eq!(None, TimeControl::parse("0/60"));
+    assert_eq!(None, TimeControl::parse("fast"));
+}
+
+#[test]
+fn epd_openings_test() {
+    let text = "# openings\n\
+        rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 id \"e4\";\n\
+        \n\
+        4k3/8/8/8/8/8/8/4K2R w K - 0 1\n";
+    let openings = read_epd_openings(text).unwrap();
+    assert_eq!(2, openings.len());
+    assert_eq!(Black, openings[0].state().to_move);
+    assert!(openings[1].state().white_can_castle_kingside);
+    assert!(read_epd_openings("8/8 w - -").is_err());
+}
+
+#[test]
+fn pgn_openings_test() {
+    let text = "[Event \"?\"]\n\
+        [White \"?\"]\n\
+        \n\
+        1.e4 e5 2. Nf3 {a comment} (2. f4 exf4) Nc6 $1 3. Bb5 a6 *\n\
+        \n\
+        [FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 1\"]\n\
+        \n\
+        1. O-O+ Kd7 1/2-1/2\n";
+    let openings = read_pgn_openings(text).unwrap();
+    assert_eq!(2, openings.len());
+    let uci: Vec<String> = {
+        let mut state = openings[0].start;
+        openings[0].moves.iter().map(|action| {
+            let text = to_uci(&state, action);
+            state = action.apply(&state);
+            text
+        }).collect()
+    };
+    assert_eq!(vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"], uci);
+    assert_eq!(ChessMove::Castle(Castle { direction: Kingside }), openings[1].moves[0]);
+    assert!(read_pgn_openings("1. e4 e4 *").is_err());
+}
+
+// Plays the moves it is given, then fails
+#[cfg(test)]
+struct ScriptedPlayer {
+    moves: Vec<&'static str>,
+    played: usize,
+}
+
+#[cfg(test)]
+impl Player for ScriptedPlayer {
+    fn name(&self) -> String {
+        "scripted".to_string()
+    }
+
+    fn select_move(&mut self, request: &MoveRequest) -> Result<ChessMove, PlayerError> {
+        let text = self.moves.get(self.played).ok_or(PlayerError::NoMove)?;
+        self.played += 1;
+        parse_uci_move(request.state, text).map_err(|_| PlayerError::IllegalMove(text.to_string()))
+    }
+}
+
+#[test]
+fn match_checkmate_and_pgn_test() {
+    let mut white = ScriptedPlayer { moves: vec!["f2f3", "g2g4"], played: 0 };
+    let mut black = ScriptedPlayer { moves: vec!["e7e5", "d8h4"], played: 0 };
+    let result = Match::new(1, TimeControl::Depth(1)).run(&mut white, &mut black);
+
+    assert_eq!((0, 0, 1), (result.wins, result.draws, result.losses));
+    let game = &result.games[0];
+    assert_eq!(GameResult::BlackWins, game.result);
+    assert_eq!(Termination::Checkmate, game.termination);
+    let pgn = game.to_pgn();
+    assert!(pgn.contains("[Result \"0-1\"]"));
+    assert!(pgn.contains("1. f3 e5 2. g4 Qh4# {checkmate} 0-1"));
+}
+
+#[test]
+fn match_illegal_move_forfeits_test() {
+    let mut first = ScriptedPlayer { moves: vec!["e2e5"], played: 0 };
+    let mut second = ScriptedPlayer { moves: vec![], played: 0 };
+    let result = Match::new(1, TimeControl::Depth(1)).run(&mut first, &mut second);
+    assert_eq!(Termination::IllegalMove, result.games[0].termination);
+    assert_eq!(GameResult::BlackWins, result.games[0].result);
+}
+
+#[test]
+fn match_alternates_colours_test() {
+    let mut first = SearchPlayer::new("first");
+    let mut second = SearchPlayer::new("second");
+    let openings = read_epd_openings("4k3/8/8/8/8/8/8/4K3 w - -\n").unwrap();
+    let result = Match::new(2, TimeControl::Depth(1))
+        .openings(openings)
+        .max_plies(4)
+        .run(&mut first, &mut second);
+
+    assert_eq!(2, result.games.len());
+    assert_eq!(("first", "second"), (result.games[0].white.as_str(), result.games[0].black.as_str()));
+    assert_eq!(("second", "first"), (result.games[1].white.as_str(), result.games[1].black.as_str()));
+    assert_eq!(Termination::InsufficientMaterial, result.games[0].termination);
+    assert_eq!(2, result.draws);
+    assert_eq!(0.5, result.score());
+    assert_eq!(2, result.to_pgn().matches("[Event ").count());
+}
+
+#[test]
+fn match_move_limit_test() {
+    let mut first = SearchPlayer::new("first");
+    let mut second = SearchPlayer::new("second");
+    let result = Match::new(1, TimeControl::Depth(1)).max_plies(2).run(&mut first, &mut second);
+    assert_eq!(2, result.games[0].moves.len());
+    assert_eq!(Termination::MoveLimit, result.games[0].termination);
+    assert!(result.summary().starts_with("first vs second: 0 wins, 1 draws, 0 losses"));
+}
+
+#[test]
+fn uci_player_start_failure_test() {
+    assert!(matches!(UciPlayer::start("/nonexistent/engine", &[]), Err(PlayerError::Io(_))));
+}
+
+#[test]
+fn elo_score_conversion_test() {
+    assert_eq!(0.5, elo_to_score(0.0));
+    assert!((score_to_elo(0.75) - 190.85).abs() < 0.01);
+    assert!((score_to_elo(elo_to_score(-123.0)) + 123.0).abs() < 1e-9);
+}
+
+#[test]
+fn elo_from_wdl_test() {
+    let estimate = elo_from_wdl(60, 20, 20);
+    assert!((estimate.elo - 147.19).abs() < 0.5);
+    assert!(estimate.error > 0.0 && estimate.error < 100.0);
+    assert!(estimate.los > 0.99);
+
+    let even = elo_from_wdl(10, 5, 10);
+    assert!(even.elo.abs() < 1e-6);
+    assert!((even.los - 0.5).abs() < 1e-6);
+
+    // More games narrow the error bars
+    assert!(elo_from_wdl(600, 200, 200).error < estimate.error);
+}
+
+#[test]
+fn elo_from_pentanomial_test() {
+    let even = elo_from_pentanomial(&[1, 2, 4, 2, 1]);
+    assert!(even.elo.abs() < 1e-6);
+    let ahead = elo_from_pentanomial(&[1, 2, 3, 3, 1]);
+    let behind = elo_from_pentanomial(&[1, 3, 3, 2, 1]);
+    assert!(ahead.elo > 0.0);
+    assert!((ahead.elo + behind.elo).abs() < 1e-6);
+    assert!((ahead.los + behind.los - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn los_test() {
+    assert_eq!(0.5, los(0, 0));
+    assert!((los(10, 10) - 0.5).abs() < 1e-6);
+    assert!((los(20, 10) - 0.9661).abs() < 1e-3);
+    assert!((los(10, 20) - 0.0339).abs() < 1e-3);
+}
+
+#[test]
+fn sprt_test() {
+    let sprt = Sprt::default();
+    let (lower, upper) = sprt.bounds();
+    assert!((lower + 2.944).abs() < 1e-3);
+    assert!((upper - 2.944).abs() < 1e-3);
+
+    assert_eq!(0.0, sprt.llr_wdl(0, 0, 0));
+    assert_eq!(SprtDecision::Continue, sprt.decision(sprt.llr_wdl(5, 5, 4)));
+    assert_eq!(SprtDecision::AcceptH1, sprt.decision(sprt.llr_wdl(600, 200, 200)));
+    assert_eq!(SprtDecision::AcceptH0, sprt.decision(sprt.llr_wdl(200, 200, 600)));
+    assert_eq!(SprtDecision::AcceptH1, sprt.decision(sprt.llr_pentanomial(&[0, 0, 100, 200, 100])));
+
+    // Every win raises the ratio, every loss lowers it
+    assert!(sprt.llr_wdl(11, 10, 10) > sprt.llr_wdl(10, 10, 10));
+    assert!(sprt.llr_wdl(10, 10, 11) < sprt.llr_wdl(10, 10, 10));
+}
+
+#[test]
+fn match_stops_on_sprt_decision_test() {
+    let mut first = SearchPlayer::new("first");
+    let mut second = SearchPlayer::new("second");
+    // With these error rates any result decides
+    let result = Match::new(6, TimeControl::Depth(1))
+        .max_plies(2)
+        .sprt(Sprt::new(0.0, 5.0, 0.5, 0.5))
+        .run(&mut first, &mut second);
+    assert_eq!(2, result.games.len());
+    assert_eq!([0, 0, 1, 0, 0], result.pentanomial());
+}
+
+#[test]
+fn random_agent_is_repeatable_test() {
+    let state = GameState::new();
+    let mut first = RandomAgent::new(7);
+    let mut second = RandomAgent::new(7);
+    for _ in 0..10 {
+        let action = first.select_action(&state);
+        assert_eq!(action, second.select_action(&state));
+        assert!(action.is_legal(&state));
+    }
+}
+
+#[test]
+fn greedy_material_agent_test() {
+    // Taking the pawn loses the queen, which the greedy agent can't see
+    let state = GameState::from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+    let action = GreedyMaterialAgent::new(1).select_action(&state);
+    assert_eq!(ChessMove::Capture(Capture { on: 35, with: 3 }), action);
+
+    let action = MinimaxAgent::new(2).select_action(&state);
+    assert_ne!(ChessMove::Capture(Capture { on: 35, with: 3 }), action);
+}
+
+#[test]
+fn capture_agent_test() {
+    let state = GameState::from_fen("4k3/8/8/p7/8/8/8/R3K2N w - - 0 1").unwrap();
+    let mut agent = CaptureAgent::new(3);
+    for _ in 0..5 {
+        assert_eq!(ChessMove::Capture(Capture { on: 32, with: 0 }), agent.select_action(&state));
+    }
+}
+
+#[test]
+fn minimax_agent_finds_mate_test() {
+    let state = GameState::from_fen("6k1/5ppp/8/8/8/8/8/R6K w - - 0 1").unwrap();
+    let action = MinimaxAgent::new(1).select_action(&state);
+    assert_eq!("a1a8", to_uci(&state, &action));
+}
+
+#[test]
+fn play_game_test() {
+    let game = play_game(&mut RandomAgent::new(1), &mut CaptureAgent::new(2));
+    assert_eq!(("random", "capture"), (game.white.as_str(), game.black.as_str()));
+    assert!(!game.moves.is_empty());
+    assert!(game.moves.len() <= 400);
+
+    let replay = play_game(&mut RandomAgent::new(1), &mut CaptureAgent::new(2));
+    assert_eq!(game.moves, replay.moves);
+    assert_eq!(game.result, replay.result);
+}
+
+#[cfg(test)]
+fn uci_action(state: &GameState, text: &str) -> usize {
+    action_index(state, &parse_uci_move(state, text).unwrap())
+}
+
+#[test]
+fn action_index_round_trip_test() {
+    let state = GameState::new();
+    let mask = legal_action_mask(&state);
+    assert_eq!(ACTION_COUNT, mask.len());
+    assert_eq!(20, mask.iter().filter(|legal| **legal).count());
+    for action in legal_chess_moves(&state) {
+        let index = action_index(&state, &action);
+        assert!(mask[index]);
+        assert_eq!(Some(action), index_to_action(&state, index));
+    }
+    assert_eq!(12 * 64 + 28, uci_action(&state, "e2e4"));
+    assert_eq!(None, index_to_action(&state, 12 * 64 + 36));
+}
+
+#[test]
+fn action_index_promotions_test() {
+    let state = GameState::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let queen = uci_action(&state, "b7b8q");
+    assert_eq!(49 * 64 + 57, queen);
+    let mut indices = vec![queen, uci_action(&state, "b7b8n"), uci_action(&state, "b7b8b"), uci_action(&state, "b7b8r")];
+    indices.sort();
+    indices.dedup();
+    assert_eq!(4, indices.len());
+}
+
+#[test]
+fn environment_step_test() {
+    let mut env = ChessEnv::new();
+    let observation = env.reset(None);
+    assert_eq!(White, observation.state.to_move);
+
+    let (observation, reward, done, info) = env.step(12 * 64 + 28).unwrap();
+    assert_eq!(Black, observation.state.to_move);
+    assert_eq!((0.0, false, 1), (reward, done, info.steps));
+    assert!(matches!(env.step(12 * 64 + 28), Err(EnvironmentError::IllegalAction(_))));
+}
+
+#[test]
+fn environment_checkmate_test() {
+    let mut env = ChessEnv::new();
+    env.reset(None);
+    let mut last = None;
+    for text in ["f2f3", "e7e5", "g2g4", "d8h4"].iter() {
+        let action = uci_action(env.state(), text);
+        last = Some(env.step(action).unwrap());
+    }
+    let (_, reward, done, info) = last.unwrap();
+    assert_eq!((1.0, true), (reward, done));
+    assert_eq!(Some(GameResult::BlackWins), info.result);
+    assert_eq!(Some(Termination::Checkmate), info.termination);
+    assert!(!info.truncated);
+    assert!(matches!(env.step(0), Err(EnvironmentError::EpisodeOver)));
+}
+
+#[test]
+fn environment_truncation_test() {
+    let mut env = ChessEnv::new().with_max_steps(2);
+    env.reset(None);
+    env.step(uci_action(env.state(), "e2e4")).unwrap();
+    let (_, reward, done, info) = env.step(uci_action(env.state(), "e7e5")).unwrap();
+    assert_eq!((0.0, true, true), (reward, done, info.truncated));
+    assert_eq!(None, info.result);
+}
+
+#[test]
+fn environment_material_shaping_test() {
+    let mut env = ChessEnv::new().with_reward_scheme(RewardScheme::material_shaping(0.1));
+    env.reset_to_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+    let (_, reward, _, _) = env.step(uci_action(env.state(), "e4d5")).unwrap();
+    assert!((reward - 0.1).abs() < 1e-9);
+    assert!(env.reset_to_fen("not a position").is_err());
+}
+
+#[test]
+fn environment_opponent_test() {
+    let mut env = ChessEnv::new().with_opponent(Box::new(MinimaxAgent::new(1)));
+    env.reset_to_fen("rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq - 0 2").unwrap();
+    let (observation, reward, done, info) = env.step(uci_action(env.state(), "g2g4")).unwrap();
+    assert_eq!((-1.0, true), (reward, done));
+    assert_eq!(Some(GameResult::BlackWins), info.result);
+    assert_eq!(White, observation.state.to_move);
+}
+
+#[test]
+fn environment_random_opening_test() {
+    let mut env = ChessEnv::new().with_random_opening(4);
+    let first = env.reset(Some(9)).state;
+    let second = env.reset(Some(9)).state;
+    assert_eq!(first.hash(), second.hash());
+    assert_eq!(White, first.to_move);
+    assert_ne!(GameState::new().hash(), first.hash());
+}
+
+#[cfg(test)]
+fn policy_index(state: &GameState, text: &str, orientation: PolicyOrientation) -> usize {
+    move_to_policy_index(state, &parse_uci_move(state, text).unwrap(), orientation)
+}
+
+#[test]
+fn policy_index_test() {
+    let state = GameState::new();
+    assert_eq!(4672, POLICY_SIZE);
+    // Two squares north, and a knight jump one left and two up
+    assert_eq!(12 * 73 + 1, policy_index(&state, "e2e4", PolicyOrientation::White));
+    assert_eq!(6 * 73 + 63, policy_index(&state, "g1f3", PolicyOrientation::White));
+
+    let state = GameState::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+    assert_eq!(4 * 73 + 15, policy_index(&state, "e1g1", PolicyOrientation::White));
+    assert_eq!(49 * 73, policy_index(&state, "b7b8q", PolicyOrientation::White));
+    assert_eq!(49 * 73 + 65, policy_index(&state, "b7b8n", PolicyOrientation::White));
+    assert_eq!(49 * 73 + 71, policy_index(&state, "b7b8r", PolicyOrientation::White));
+}
+
+#[test]
+fn policy_orientation_test() {
+    let state = GameState::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+    assert_eq!(12 * 73 + 1, policy_index(&state, "e7e5", PolicyOrientation::SideToMove));
+    assert_eq!(52 * 73 + 29, policy_index(&state, "e7e5", PolicyOrientation::White));
+
+    let action = parse_uci_move(&state, "g8f6").unwrap();
+    let index = move_to_policy_index(&state, &action, PolicyOrientation::SideToMove);
+    assert_eq!(Some(action), policy_index_to_move(&state, index, PolicyOrientation::SideToMove));
+    assert_eq!(None, policy_index_to_move(&state, index, PolicyOrientation::White));
+}
+
+#[test]
+fn policy_mask_test() {
+    let fens = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+        "4k3/1P6/8/3pP3/8/8/6p1/4K3 w - d6 0 1",
+    ];
+    for fen in fens.iter() {
+        let state = GameState::from_fen(fen).unwrap();
+        let moves = legal_chess_moves(&state);
+        for orientation in [PolicyOrientation::White, PolicyOrientation::SideToMove].iter() {
+            let mask = policy_legal_mask(&state, *orientation);
+            assert_eq!(POLICY_SIZE, mask.len());
+            assert_eq!(moves.len(), mask.iter().filter(|legal| **legal).count());
+            for action in moves.iter() {
+                let index = move_to_policy_index(&state, action, *orientation);
+                assert_eq!(Some(*action), policy_index_to_move(&state, index, *orientation));
+            }
+        }
+    }
+    assert_eq!(None, policy_index_to_move(&GameState::new(), POLICY_SIZE, PolicyOrientation::White));
+}
+
+#[cfg(test)]
+fn plane_sum(planes: &[f32], plane: usize) -> f32 {
+    planes[64 * plane..64 * (plane + 1)].iter().sum()
+}
+
+#[test]
+fn planes_initial_position_test() {
+    let encoder = PlaneEncoder::new();
+    assert_eq!(20, encoder.plane_count());
+    assert_eq!(20 * 64, encoder.size());
+    assert_eq!(56, PlaneEncoder::new().with_history(4).plane_count());
+
+    let mut planes = vec![0.0f32; encoder.size()];
+    encoder.encode_state(&GameState::new(), &mut planes);
+    let counts = [8.0, 2.0, 2.0, 2.0, 1.0, 1.0];
+    for (kind, count) in counts.iter().enumerate() {
+        assert_eq!(*count, plane_sum(&planes, kind));
+        assert_eq!(*count, plane_sum(&planes, 6 + kind));
+    }
+    assert!((8..16).all(|square| planes[square] == 1.0));
+    assert_eq!(1.0, planes[5 * 64 + 4]);
+    assert_eq!(1.0, planes[11 * 64 + 60]);
+    // White to move, all castling rights, no en passant, move 1
+    assert_eq!(0.0, plane_sum(&planes, 12));
+    assert!((13..17).all(|plane| plane_sum(&planes, plane) == 64.0));
+    assert_eq!(0.0, plane_sum(&planes, 17));
+    assert_eq!(0.0, plane_sum(&planes, 18));
+    assert_eq!(64.0, plane_sum(&planes, 19));
+}
+
+#[test]
+fn planes_orientation_test() {
+    let state = GameState::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+
+    let encoder = PlaneEncoder::new();
+    let mut planes = vec![0.0f32; encoder.size()];
+    encoder.encode_state(&state, &mut planes);
+    // Black's pawns come first, seen from black's side of the board
+    assert!((8..16).all(|square| planes[square] == 1.0));
+    assert_eq!(1.0, planes[6 * 64 + (28 ^ 56)]);
+    assert_eq!(64.0, plane_sum(&planes, 12));
+    assert_eq!(1.0, planes[17 * 64 + (20 ^ 56)]);
+    assert_eq!(1.0, plane_sum(&planes, 17));
+
+    let encoder = PlaneEncoder::new().with_orientation(PolicyOrientation::White);
+    encoder.encode_state(&state, &mut planes);
+    assert_eq!(1.0, planes[28]);
+    assert!((48..56).all(|square| planes[6 * 64 + square] == 1.0));
+    assert_eq!(1.0, planes[17 * 64 + 20]);
+}
+
+#[test]
+fn planes_castling_test() {
+    let state = GameState::from_fen("r3k2r/8/8/8/8/8/8/4K3 b kq - 0 1").unwrap();
+    let mut planes = vec![0.0f32; PlaneEncoder::new().size()];
+
+    PlaneEncoder::new().encode_state(&state, &mut planes);
+    assert_eq!([64.0, 64.0, 0.0, 0.0], [13, 14, 15, 16].map(|plane| plane_sum(&planes, plane)));
+
+    PlaneEncoder::new().with_orientation(PolicyOrientation::White).encode_state(&state, &mut planes);
+    assert_eq!([0.0, 0.0, 64.0, 64.0], [13, 14, 15, 16].map(|plane| plane_sum(&planes, plane)));
+}
+
+#[test]
+fn planes_history_test() {
+    let encoder = PlaneEncoder::new().with_history(3);
+    let mut planes = vec![0.0f32; encoder.size()];
+
+    // There's no history before the first position
+    let mut history = PositionHistory::new(GameState::new(), 3);
+    encoder.encode_history(&history, &mut planes);
+    assert_eq!(32.0, (0..12).map(|plane| plane_sum(&planes, plane)).sum::<f32>());
+    assert!((12..36).all(|plane| plane_sum(&planes, plane) == 0.0));
+
+    for text in ["d2d4", "d7d6", "e2e4", "e7e5", "g1f3"].iter() {
+        let action = parse_uci_move(history.current(), text).unwrap();
+        history.push(&action);
+    }
+    encoder.encode_history(&history, &mut planes);
+    // Black to move, so white's knights are planes 7, 19 and 31
+    assert_eq!(1.0, planes[7 * 64 + (21 ^ 56)]);
+    assert_eq!(1.0, planes[19 * 64 + (6 ^ 56)]);
+    assert_eq!(0.0, planes[19 * 64 + (21 ^ 56)]);
+    // Before e7e5, black's pawn was still on e7
+    assert_eq!(1.0, planes[24 * 64 + (52 ^ 56)]);
+    assert_eq!(0.0, planes[12 * 64 + (52 ^ 56)]);
+    // One quiet move since e7e5, and black's third move
+    assert_eq!(1, history.halfmove_clock());
+    assert_eq!(3, history.fullmove_number());
+    assert_eq!(64.0, plane_sum(&planes, 42));
+    assert_eq!(192.0, plane_sum(&planes, 43));
+
+    let mut bytes = vec![0u8; encoder.size()];
+    encoder.encode_history(&history, &mut bytes);
+    assert!(bytes.iter().zip(planes.iter()).all(|(byte, value)| *byte as f32 == *value));
+
+    // The same as encoding every position directly, of which only the
+    // last three are used
+    let mut direct = vec![0.0f32; encoder.size()];
+    let mut positions = vec![GameState::new()];
+    for text in ["d2d4", "d7d6", "e2e4", "e7e5", "g1f3"].iter() {
+        let state = *positions.last().unwrap();
+        positions.push(parse_uci_move(&state, text).unwrap().apply(&state));
+    }
+    encoder.encode(&positions, 1, 3, &mut direct);
+    assert_eq!(planes, direct);
+}
+
+#[test]
+fn mcts_finds_mate_test() {
+    let state = GameState::from_fen("6k1/5ppp/8/8/8/8/8/R6K w - - 0 1").unwrap();
+    let mut mcts = Mcts::new(UniformEvaluator).with_simulations(300);
+    mcts.set_position(&state);
+    mcts.search();
+    assert_eq!(300, mcts.root_visits());
+    assert_eq!(Some(parse_uci_move(&state, "a1a8").unwrap()), mcts.select_move(0.0));
+    assert!(mcts.root_value() > 0.5);
+
+    let visits: u32 = mcts.visit_counts().iter().map(|(_, visits)| visits).sum();
+    assert_eq!(299, visits);
+}
+
+#[cfg(test)]
+struct BatchCounter {
+    largest: usize,
+}
+
+#[cfg(test)]
+impl PolicyValueEvaluator for BatchCounter {
+    fn evaluate(&mut self, state: &GameState) -> (Vec<f32>, f32) {
+        UniformEvaluator.evaluate(state)
+    }
+
+    fn evaluate_batch(&mut self, states: &[GameState]) -> Vec<(Vec<f32>, f32)> {
+        self.largest = self.largest.max(states.len());
+        states.iter().map(|state| self.evaluate(state)).collect()
+    }
+}
+
+#[test]
+fn mcts_batch_test() {
+    let state = GameState::from_fen("6k1/5ppp/8/8/8/8/8/R6K w - - 0 1").unwrap();
+    let mut mcts = Mcts::new(BatchCounter { largest: 0 })
+        .with_simulations(300)
+        .with_batch_size(8);
+    mcts.set_position(&state);
+    mcts.search();
+    assert_eq!(300, mcts.root_visits());
+    assert_eq!(Some(parse_uci_move(&state, "a1a8").unwrap()), mcts.select_move(0.0));
+    // Virtual losses spread each batch over different leaves
+    let mut mcts = Mcts::new(BatchCounter { largest: 0 })
+        .with_simulations(20)
+        .with_batch_size(8);
+    mcts.search();
+    let visits = mcts.visit_counts();
+    assert!(visits.iter().filter(|(_, visits)| *visits > 0).count() >= 8);
+}
+
+#[test]
+fn mcts_priors_and_policy_test() {
+    let e4 = parse_uci_move(&GameState::new(), "e2e4").unwrap();
+    let preferred = move_to_policy_index(&GameState::new(), &e4, PolicyOrientation::SideToMove);
+    let evaluator = move |_state: &GameState| {
+        let mut priors = vec![0.01; POLICY_SIZE];
+        priors[preferred] = 1.0;
+        (priors, 0.0)
+    };
+    let mut mcts = Mcts::new(evaluator).with_simulations(50);
+    mcts.search();
+    assert_eq!(Some(e4), mcts.select_move(0.0));
+
+    let policy = mcts.visit_policy(PolicyOrientation::White);
+    assert_eq!(POLICY_SIZE, policy.len());
+    assert!((policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    let mask = policy_legal_mask(&GameState::new(), PolicyOrientation::White);
+    assert!(policy.iter().zip(mask.iter()).all(|(share, legal)| *legal || *share == 0.0));
+    assert!(policy[preferred] > 0.5);
+}
+
+#[test]
+fn mcts_noise_and_temperature_test() {
+    let counts = |seed: u64| {
+        let mut mcts = Mcts::new(UniformEvaluator)
+            .with_simulations(40)
+            .with_dirichlet_noise(0.3, 0.25)
+            .with_seed(seed);
+        mcts.search();
+        let choices: Vec<ChessMove> = (0..5).map(|_| mcts.select_move(1.0).unwrap()).collect();
+        (mcts.visit_counts(), choices)
+    };
+    assert_eq!(counts(1), counts(1));
+    assert_ne!(counts(1).0, counts(2).0);
+
+    let mut mcts = Mcts::new(UniformEvaluator).with_simulations(40);
+    mcts.search();
+    let visited: Vec<ChessMove> = mcts
+        .visit_counts()
+        .into_iter()
+        .filter(|(_, visits)| *visits > 0)
+        .map(|(action, _)| action)
+        .collect();
+    for _ in 0..20 {
+        assert!(visited.contains(&mcts.select_move(1.0).unwrap()));
+    }
+}
+
+#[test]
+fn mcts_tree_reuse_test() {
+    let mut mcts = Mcts::new(UniformEvaluator).with_simulations(100);
+    mcts.search();
+    let action = mcts.select_move(0.0).unwrap();
+    let after = action.apply(&GameState::new());
+    let kept = mcts
+        .visit_counts()
+        .into_iter()
+        .find(|(played, _)| *played == action)
+        .unwrap()
+        .1;
+    mcts.advance(&action);
+    assert_eq!(after.hash(), mcts.root_state().hash());
+    assert_eq!(kept, mcts.root_visits());
+
+    // Two moves on is still in the tree, anywhere else isn't
+    let mut mcts = Mcts::new(UniformEvaluator).with_simulations(400);
+    mcts.search();
+    let state = parse_uci_move(&GameState::new(), "a2a3").unwrap().apply(&GameState::new());
+    let state = parse_uci_move(&state, "a7a6").unwrap().apply(&state);
+    mcts.set_position(&state);
+    assert!(mcts.root_visits() > 0);
+    mcts.set_position(&GameState::from_fen("6k1/5ppp/8/8/8/8/8/R6K w - - 0 1").unwrap());
+    assert_eq!(0, mcts.root_visits());
+
+    // As an agent, the tree is kept after the move played
+    let mut mcts = Mcts::new(UniformEvaluator).with_simulations(30);
+    let first = mcts.select_action(&GameState::new());
+    assert_eq!(first.apply(&GameState::new()).hash(), mcts.root_state().hash());
+    assert!(mcts.root_visits() > 0);
+}
+
+#[test]
+fn mcts_repetition_after_reroot_test() {
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    let evaluator = move |state: &GameState| {
+        let mut priors = vec![0.01; POLICY_SIZE];
+        for text in shuffle.iter() {
+            if let Ok(action) = parse_uci_move(state, text) {
+                priors[move_to_policy_index(state, &action, PolicyOrientation::SideToMove)] = 1.0;
+            }
+        }
+        (priors, 0.0)
+    };
+    let mut mcts = Mcts::new(evaluator).with_simulations(200);
+    let mut state = GameState::new();
+    for text in shuffle.iter() {
+        mcts.set_position(&state);
+        mcts.search();
+        let action = parse_uci_move(&state, text).unwrap();
+        mcts.advance(&action);
+        state = action.apply(&state);
+    }
+    // Back at the start, which was a draw by repetition inside the old tree
+    assert_eq!(GameState::new().hash(), mcts.root_state().hash());
+    mcts.search();
+    assert!(mcts.select_move(0.0).is_some());
+    mcts.select_action(&state);
+}
@@ -0,0 +1,225 @@
+// The single responsibility of this module is to remember what searches
+// have learned about positions, so that a position reached again through
+// a different order of moves doesn't have to be searched again.
+
+use crate::actions::ChessMove;
+
+use crate::search::MATE_THRESHOLD;
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub const DEFAULT_TABLE_MEGABYTES: usize = 16;
+
+// What a stored score says about the true score of a position
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum Bound {
+    // The score is exact
+    Exact,
+    // The true score is at least this high
+    Lower,
+    // The true score is at most this high
+    Upper,
+}
+
+#[derive(Debug)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct TableEntry {
+    pub key: u64,
+    pub depth: usize,
+    pub bound: Bound,
+    // Mate scores are stored relative to the position, not the root.
+    // Use score_from_table to read them back.
+    pub score: i32,
+    pub best_move: Option<ChessMove>,
+    generation: u8,
+}
+
+// Each bucket holds two entries. The first keeps the deepest result seen
+// for any key that maps to the bucket, unless that result is left over
+// from an earlier search. The second always takes the newest result.
+#[derive(Copy)]
+#[derive(Clone)]
+struct Bucket {
+    deep: Option<TableEntry>,
+    recent: Option<TableEntry>,
+}
+
+// The buckets are split into stripes, each behind its own lock, so that
+// threads searching in parallel rarely wait for each other. Every method
+// takes &self, so one table can be shared between threads in an Arc.
+pub struct TranspositionTable {
+    stripes: Vec<Mutex<Vec<Bucket>>>,
+    generation: AtomicU8,
+}
+
+const STRIPES: usize = 64;
+
+const EMPTY_BUCKET: Bucket = Bucket { deep: None, recent: None };
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let table = TranspositionTable {
+            stripes: (0..STRIPES).map(|_| Mutex::new(vec![])).collect(),
+            generation: AtomicU8::new(0),
+        };
+        table.resize(megabytes);
+        table
+    }
+
+    // Change the size of the table, discarding everything in it
+    pub fn resize(&self, megabytes: usize) {
+        let bytes = megabytes.max(1) * 1024 * 1024;
+        let per_stripe = (bytes / std::mem::size_of::<Bucket>() / STRIPES).max(1);
+        for stripe in self.stripes.iter() {
+            *stripe.lock().unwrap() = vec![EMPTY_BUCKET; per_stripe];
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for stripe in self.stripes.iter() {
+            for bucket in stripe.lock().unwrap().iter_mut() {
+                *bucket = EMPTY_BUCKET;
+            }
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    // Mark the start of a new search. Entries from earlier searches are
+    // still used, but are the first to be replaced.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TableEntry> {
+        let stripe = self.stripes[stripe_index(key)].lock().unwrap();
+        let bucket = &stripe[bucket_index(key, stripe.len())];
+        [bucket.deep, bucket.recent]
+            .iter()
+            .flatten()
+            .find(|entry| entry.key == key)
+            .copied()
+    }
+
+    pub fn store(
+        &self,
+        key: u64,
+        depth: usize,
+        bound: Bound,
+        score: i32,
+        best_move: Option<ChessMove>,
+    ) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let mut stripe = self.stripes[stripe_index(key)].lock().unwrap();
+        let index = bucket_index(key, stripe.len());
+        let bucket = &mut stripe[index];
+
+        // Don't forget the best move of a position just because the
+        // latest search of it didn't find one
+        let previous_move = [bucket.deep, bucket.recent]
+            .iter()
+            .flatten()
+            .find(|entry| entry.key == key)
+            .and_then(|entry| entry.best_move);
+
+        let entry = TableEntry {
+            key,
+            depth,
+            bound,
+            score,
+            best_move: best_move.or(previous_move),
+            generation,
+        };
+
+        let replace_deep = match bucket.deep {
+            None => true,
+            Some(deep) => {
+                deep.key == key || deep.generation != generation || depth >= deep.depth
+            },
+        };
+
+        if replace_deep {
+            // Keep the displaced entry around if it's for a different position
+            if let Some(deep) = bucket.deep {
+                if deep.key != key {
+                    bucket.recent = Some(deep);
+                } else if let Some(recent) = bucket.recent {
+                    if recent.key == key {
+                        bucket.recent = None;
+                    }
+                }
+            }
+            bucket.deep = Some(entry);
+        } else {
+            bucket.recent = Some(entry);
+        }
+    }
+
+    // The number of entries the table can hold
+    pub fn capacity(&self) -> usize {
+        2 * STRIPES * self.stripes[0].lock().unwrap().len()
+    }
+
+    // How full the table is, in parts per thousand, estimated from a sample
+    // of buckets. Only entries from the current search are counted.
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let mut sampled = 0;
+        let mut used = 0;
+        for stripe in self.stripes.iter() {
+            for bucket in stripe.lock().unwrap().iter().take(1000 / STRIPES + 1) {
+                sampled += 1;
+                for entry in [bucket.deep, bucket.recent].iter().flatten() {
+                    if entry.generation == generation {
+                        used += 1;
+                    }
+                }
+            }
+        }
+        used * 1000 / (2 * sampled)
+    }
+}
+
+// Consecutive keys go to different stripes, and keys a whole number of
+// tables apart share a bucket
+fn stripe_index(key: u64) -> usize {
+    (key % STRIPES as u64) as usize
+}
+
+fn bucket_index(key: u64, buckets: usize) -> usize {
+    ((key / STRIPES as u64) % buckets as u64) as usize
+}
+
+impl Default for TranspositionTable {
+    fn default() -> TranspositionTable {
+        TranspositionTable::new(DEFAULT_TABLE_MEGABYTES)
+    }
+}
+
+// Mate scores count plies from the root of the search. In the table they
+// are stored counting from the position itself, so they stay correct when
+// the same position is reached at a different ply.
+pub fn score_to_table(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply
+    } else if score <= -MATE_THRESHOLD {
+        score - ply
+    } else {
+        score
+    }
+}
+
+pub fn score_from_table(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply
+    } else if score <= -MATE_THRESHOLD {
+        score + ply
+    } else {
+        score
+    }
+}
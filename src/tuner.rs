@@ -0,0 +1,218 @@
+// The single responsibility of this module is to fit the weights of the
+// classical evaluation to positions whose game results are known.
+
+use crate::gamestate::{
+    GameState,
+    FenError,
+};
+
+use crate::pieces::Color::{White, Black};
+
+use crate::evaluation::{
+    Evaluator,
+    ClassicalEvaluator,
+    EvaluationWeights,
+};
+
+use std::path::Path;
+
+// A position, and the result of the game it was taken from as a score
+// for white: 1 for a win, 0.5 for a draw and 0 for a loss
+#[derive(Debug)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub struct LabelledPosition {
+    pub state: GameState,
+    pub result: f64,
+}
+
+#[derive(Debug)]
+pub enum TunerError {
+    Io(std::io::Error),
+    // The line number, counting from 1, and what was wrong with it
+    InvalidFen(usize, FenError),
+    MissingResult(usize),
+    InvalidResult(usize, String),
+}
+
+// Read one position per line, as a FEN followed by the result. Results
+// may be written as in PGN ("1-0", "0-1", "1/2-1/2") or as a number
+// ("1.0", "0.5", "0.0"), and may be quoted or bracketed, so EPD lines
+// like `<fen> c9 "1/2-1/2";` and `<fen> [0.5]` both work. Blank lines and
+// lines starting with '#' are ignored.
+pub fn read_labelled_positions(text: &str) -> Result<Vec<LabelledPosition>, TunerError> {
+    let mut positions = vec![];
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields: Vec<&str> = line
+            .split_whitespace()
+            .filter(|field| *field != "c9")
+            .collect();
+        if fields.len() < 5 {
+            return Err(TunerError::MissingResult(number));
+        }
+
+        let label = fields.pop().unwrap();
+        let result = parse_result(label)
+            .ok_or_else(|| TunerError::InvalidResult(number, label.to_string()))?;
+        let state = GameState::from_fen(&fields.join(" "))
+            .map_err(|error| TunerError::InvalidFen(number, error))?;
+
+        positions.push(LabelledPosition { state, result });
+    }
+
+    Ok(positions)
+}
+
+pub fn load_labelled_positions<P: AsRef<Path>>(path: P) -> Result<Vec<LabelledPosition>, TunerError> {
+    let text = std::fs::read_to_string(path).map_err(TunerError::Io)?;
+    read_labelled_positions(&text)
+}
+
+fn parse_result(label: &str) -> Option<f64> {
+    let label = label.trim_matches(|c| c == '"' || c == '[' || c == ']' || c == ';');
+    match label {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => label.parse::<f64>().ok().filter(|result| (0.0..=1.0).contains(result)),
+    }
+}
+
+// Texel's tuning method. The evaluation of each position is mapped to an
+// expected score between 0 and 1 by a sigmoid, and weights are adjusted
+// one at a time to reduce the mean squared difference between expected
+// scores and game results. Positions should be quiet, since they are
+// evaluated without searching.
+pub struct Tuner {
+    positions: Vec<LabelledPosition>,
+    scaling: f64,
+    step: i32,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<LabelledPosition>) -> Tuner {
+        Tuner { positions, scaling: 1.0, step: 1 }
+    }
+
+    // How steep the sigmoid is. A scaling of 1 means an advantage of 400
+    // centipawns is expected to score about 0.91.
+    pub fn scaling(mut self, scaling: f64) -> Tuner {
+        self.scaling = scaling;
+        self
+    }
+
+    // How far each weight is moved when trying to improve it
+    pub fn step(mut self, step: i32) -> Tuner {
+        self.step = step.max(1);
+        self
+    }
+
+    pub fn positions(&self) -> &[LabelledPosition] {
+        &self.positions
+    }
+
+    // Choose the scaling that best fits `weights` as they are, which should
+    // be done before tuning so that the weights keep their scale
+    pub fn fit_scaling(&mut self, weights: &EvaluationWeights) -> f64 {
+        let evaluator = ClassicalEvaluator::new(weights.clone());
+        let scores: Vec<i32> = self.positions
+            .iter()
+            .map(|position| white_score(&evaluator, &position.state))
+            .collect();
+
+        // The error is convex in the scaling, so narrow in on the minimum
+        let (mut low, mut high) = (0.0, 10.0);
+        for _ in 0..100 {
+            let a = low + (high - low) / 3.0;
+            let b = high - (high - low) / 3.0;
+            if self.scores_error(&scores, a) < self.scores_error(&scores, b) {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+        self.scaling = (low + high) / 2.0;
+        self.scaling
+    }
+
+    // The mean squared difference between expected scores and results
+    pub fn error(&self, weights: &EvaluationWeights) -> f64 {
+        let evaluator = ClassicalEvaluator::new(weights.clone());
+        let scores: Vec<i32> = self.positions
+            .iter()
+            .map(|position| white_score(&evaluator, &position.state))
+            .collect();
+        self.scores_error(&scores, self.scaling)
+    }
+
+    // Try moving every weight up and then down by the step, keeping each
+    // change that lowers the error. Returns whether anything changed.
+    pub fn tune_pass(&self, weights: &mut EvaluationWeights) -> bool {
+        let mut parameters = weights.parameters();
+        let mut best_error = self.error(weights);
+        let mut improved = false;
+
+        for i in 0..parameters.len() {
+            let original = parameters[i];
+            for candidate in [original + self.step, original - self.step].iter() {
+                parameters[i] = *candidate;
+                weights.set_parameters(&parameters);
+                let error = self.error(weights);
+                if error < best_error {
+                    best_error = error;
+                    improved = true;
+                    break;
+                }
+                parameters[i] = original;
+                weights.set_parameters(&parameters);
+            }
+        }
+
+        improved
+    }
+
+    // Run up to `passes` passes, stopping early once no weight can be
+    // improved, and return the tuned weights
+    pub fn tune(&self, weights: &EvaluationWeights, passes: usize) -> EvaluationWeights {
+        let mut weights = weights.clone();
+        for _ in 0..passes {
+            if !self.tune_pass(&mut weights) {
+                break;
+            }
+        }
+        weights
+    }
+
+    fn scores_error(&self, scores: &[i32], scaling: f64) -> f64 {
+        if self.positions.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = self.positions
+            .iter()
+            .zip(scores.iter())
+            .map(|(position, score)| (position.result - sigmoid(*score, scaling)).powi(2))
+            .sum();
+        total / self.positions.len() as f64
+    }
+}
+
+// The expected score for white of a position white is ahead in by `score`
+// centipawns
+pub fn sigmoid(score: i32, scaling: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling * score as f64 / 400.0))
+}
+
+fn white_score(evaluator: &dyn Evaluator, state: &GameState) -> i32 {
+    let score = evaluator.evaluate(state);
+    match state.to_move {
+        White => score,
+        Black => -score,
+    }
+}
@@ -0,0 +1,372 @@
+// The single responsibility of this module is to let chess GUIs and
+// tournament managers drive the search through the Universal Chess
+// Interface, reading commands line by line and writing replies.
+
+use crate::gamestate::GameState;
+
+use crate::actions::Action;
+
+use crate::notation::{
+    to_uci,
+    parse_uci_move,
+};
+
+use crate::search::{
+    Search,
+    SearchLimits,
+    SearchInfo,
+    mate_distance,
+};
+
+use crate::transposition::{
+    TranspositionTable,
+    DEFAULT_TABLE_MEGABYTES,
+};
+
+use crate::evaluation::{
+    Evaluator,
+    ClassicalEvaluator,
+};
+
+use crate::nnue::NnueEvaluator;
+
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const MAX_TABLE_MEGABYTES: usize = 4096;
+const MAX_THREADS: usize = 256;
+const MAX_LINES: usize = 256;
+
+// How often a finished infinite or ponder search checks whether it may
+// report its best move
+const HOLD_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+// A UCI engine writing to `output`. Searches run on a background thread,
+// so that `stop` and `ponderhit` are handled while they think.
+pub struct UciEngine<W: Write + Send + 'static> {
+    output: Arc<Mutex<W>>,
+    state: GameState,
+    table: Arc<TranspositionTable>,
+    evaluator: Arc<dyn Evaluator>,
+    threads: usize,
+    lines: usize,
+    running: Option<RunningSearch>,
+}
+
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    // Raised while the best move has to be held back even if the search
+    // finishes: during infinite searches and while pondering
+    hold: Arc<AtomicBool>,
+    // How long to think once the opponent plays the move pondered on
+    ponder_time: Option<Duration>,
+    handle: JoinHandle<()>,
+}
+
+impl<W: Write + Send + 'static> UciEngine<W> {
+    pub fn new(output: W) -> UciEngine<W> {
+        UciEngine {
+            output: Arc::new(Mutex::new(output)),
+            state: GameState::new(),
+            table: Arc::new(TranspositionTable::new(DEFAULT_TABLE_MEGABYTES)),
+            evaluator: Arc::new(ClassicalEvaluator::default()),
+            threads: 1,
+            lines: 1,
+            running: None,
+        }
+    }
+
+    // Handle commands until `quit` or the end of the input, then wait for
+    // any search still running to report its move
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if !self.handle(&line) {
+                return;
+            }
+        }
+        self.finish_search();
+    }
+
+    // Handle one command. Returns false once the engine should exit.
+    // Unknown commands are ignored, as the protocol asks.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => self.identify(),
+            Some(&"isready") => self.send("readyok"),
+            Some(&"ucinewgame") => {
+                self.stop_search();
+                self.table.clear();
+                self.state = GameState::new();
+            },
+            Some(&"position") => {
+                self.stop_search();
+                self.set_position(&tokens[1..]);
+            },
+            Some(&"go") => {
+                self.stop_search();
+                self.go(&tokens[1..]);
+            },
+            Some(&"stop") => self.stop_search(),
+            Some(&"ponderhit") => self.ponderhit(),
+            Some(&"setoption") => {
+                self.stop_search();
+                self.set_option(&tokens[1..]);
+            },
+            Some(&"quit") => {
+                self.stop_search();
+                return false;
+            },
+            _ => (),
+        }
+        true
+    }
+
+    fn send(&self, text: &str) {
+        send(&self.output, text);
+    }
+
+    fn identify(&self) {
+        self.send(&format!("id name {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+        self.send(&format!("id author {}", env!("CARGO_PKG_AUTHORS")));
+        self.send(&format!(
+            "option name Hash type spin default {} min 1 max {}",
+            DEFAULT_TABLE_MEGABYTES,
+            MAX_TABLE_MEGABYTES,
+        ));
+        self.send(&format!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS));
+        self.send(&format!("option name MultiPV type spin default 1 min 1 max {}", MAX_LINES));
+        self.send("option name Ponder type check default false");
+        self.send("option name EvalFile type string default <empty>");
+        self.send("option name Clear Hash type button");
+        self.send("uciok");
+    }
+
+    // position startpos|fen <fen> [moves <move>...]
+    fn set_position(&mut self, tokens: &[&str]) {
+        let moves_at = tokens.iter().position(|token| *token == "moves").unwrap_or(tokens.len());
+        let state = match tokens.first() {
+            Some(&"startpos") => GameState::new(),
+            Some(&"fen") => match GameState::from_fen(&tokens[1..moves_at].join(" ")) {
+                Ok(state) => state,
+                Err(error) => {
+                    self.send(&format!("info string invalid fen: {:?}", error));
+                    return;
+                },
+            },
+            _ => return,
+        };
+
+        self.state = state;
+        for text in tokens.iter().skip(moves_at + 1) {
+            match parse_uci_move(&self.state, text) {
+                Ok(action) => self.state = action.apply(&self.state),
+                Err(_) => {
+                    self.send(&format!("info string illegal move: {}", text));
+                    return;
+                },
+            }
+        }
+    }
+
+    fn go(&mut self, tokens: &[&str]) {
+        let mut limits = SearchLimits::new();
+        let mut clock = [None, None, Some(0), Some(0)];
+        let mut infinite = false;
+        let mut ponder = false;
+        let mut root_moves = vec![];
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let value = tokens.get(i + 1).and_then(|value| value.parse::<u64>().ok());
+            match (tokens[i], value) {
+                ("wtime", Some(ms)) => clock[0] = Some(ms),
+                ("btime", Some(ms)) => clock[1] = Some(ms),
+                ("winc", Some(ms)) => clock[2] = Some(ms),
+                ("binc", Some(ms)) => clock[3] = Some(ms),
+                ("movestogo", Some(moves)) => limits = limits.moves_to_go(moves as u32),
+                ("depth", Some(depth)) => limits = limits.depth(depth as usize),
+                ("nodes", Some(nodes)) => limits = limits.nodes(nodes),
+                ("movetime", Some(ms)) => limits = limits.move_time(Duration::from_millis(ms)),
+                // A mate in n moves is found within 2n - 1 plies
+                ("mate", Some(moves)) => limits = limits.depth((2 * moves as usize).max(2) - 1),
+                ("infinite", _) => infinite = true,
+                ("ponder", _) => ponder = true,
+                ("searchmoves", _) => {
+                    while let Some(action) = tokens.get(i + 1).and_then(|text| parse_uci_move(&self.state, text).ok()) {
+                        root_moves.push(action);
+                        i += 1;
+                    }
+                },
+                _ => (),
+            }
+            i += if value.is_some() { 2 } else { 1 };
+        }
+
+        if clock[0].is_some() || clock[1].is_some() {
+            let ms = |value: Option<u64>| Duration::from_millis(value.unwrap_or(0));
+            limits = limits.clock(ms(clock[0]), ms(clock[1]), ms(clock[2]), ms(clock[3]));
+        }
+
+        // While pondering, the clock doesn't apply yet. It starts when the
+        // opponent plays the expected move.
+        let ponder_time = limits.time_budget(self.state.to_move).map(|budget| budget.soft);
+        if ponder {
+            limits = limits.without_time();
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let hold = Arc::new(AtomicBool::new(infinite || ponder));
+        let mut search = Search::new(limits)
+            .with_stop_flag(Arc::clone(&stop))
+            .with_transposition_table(Arc::clone(&self.table))
+            .with_evaluator(Arc::clone(&self.evaluator))
+            .with_threads(self.threads)
+            .with_multi_pv(self.lines);
+        if !root_moves.is_empty() {
+            search = search.with_root_moves(root_moves);
+        }
+
+        let output = Arc::clone(&self.output);
+        let table = Arc::clone(&self.table);
+        let state = self.state;
+        let mut search = search.on_iteration(move |info| {
+            send(&output, &info_line(&state, info, table.hashfull()));
+        });
+
+        let output = Arc::clone(&self.output);
+        let (thread_stop, thread_hold) = (Arc::clone(&stop), Arc::clone(&hold));
+        let handle = std::thread::spawn(move || {
+            let result = search.run(&state);
+            while thread_hold.load(Ordering::Relaxed) && !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(HOLD_POLL_INTERVAL);
+            }
+
+            let moves = &result.principal_variation_moves;
+            let reply = match (moves.first(), moves.get(1)) {
+                (Some(best), Some(next)) => format!(
+                    "bestmove {} ponder {}",
+                    to_uci(&state, best),
+                    to_uci(&best.apply(&state), next),
+                ),
+                (Some(best), None) => format!("bestmove {}", to_uci(&state, best)),
+                _ => "bestmove 0000".to_string(),
+            };
+            send(&output, &reply);
+        });
+
+        self.running = Some(RunningSearch {
+            stop,
+            hold,
+            ponder_time: if ponder { ponder_time } else { None },
+            handle,
+        });
+    }
+
+    // The opponent played the move being pondered on, so the search goes
+    // on as a normal timed search
+    fn ponderhit(&mut self) {
+        let running = match self.running.as_mut() {
+            Some(running) => running,
+            None => return,
+        };
+        running.hold.store(false, Ordering::Relaxed);
+        if let Some(time) = running.ponder_time.take() {
+            let stop = Arc::clone(&running.stop);
+            std::thread::spawn(move || {
+                std::thread::sleep(time);
+                stop.store(true, Ordering::Relaxed);
+            });
+        }
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(running) = self.running.as_ref() {
+            running.stop.store(true, Ordering::Relaxed);
+        }
+        self.finish_search();
+    }
+
+    // Wait for the running search, if any, to report its move. Searches
+    // that would wait for `stop` forever are stopped.
+    fn finish_search(&mut self) {
+        if let Some(running) = self.running.take() {
+            if running.hold.load(Ordering::Relaxed) {
+                running.stop.store(true, Ordering::Relaxed);
+            }
+            running.handle.join().unwrap();
+        }
+    }
+
+    // setoption name <name> [value <value>]
+    fn set_option(&mut self, tokens: &[&str]) {
+        let value_at = tokens.iter().position(|token| *token == "value").unwrap_or(tokens.len());
+        if tokens.first() != Some(&"name") {
+            return;
+        }
+        let name = tokens[1..value_at].join(" ").to_lowercase();
+        let value = tokens.get(value_at + 1..).map(|value| value.join(" ")).unwrap_or_default();
+        let number = value.parse::<usize>().ok();
+
+        match (name.as_str(), number) {
+            ("hash", Some(megabytes)) => self.table.resize(megabytes.clamp(1, MAX_TABLE_MEGABYTES)),
+            ("threads", Some(threads)) => self.threads = threads.clamp(1, MAX_THREADS),
+            ("multipv", Some(lines)) => self.lines = lines.clamp(1, MAX_LINES),
+            ("clear hash", _) => self.table.clear(),
+            ("evalfile", _) => {
+                if value.is_empty() || value == "<empty>" {
+                    self.evaluator = Arc::new(ClassicalEvaluator::default());
+                    return;
+                }
+                match NnueEvaluator::from_file(&value) {
+                    Ok(evaluator) => self.evaluator = Arc::new(evaluator),
+                    Err(error) => self.send(&format!("info string couldn't load {}: {:?}", value, error)),
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+fn send<W: Write>(output: &Mutex<W>, text: &str) {
+    let mut output = output.lock().unwrap();
+    // There is nobody to tell if the GUI has gone away
+    let _ = writeln!(output, "{}", text);
+    let _ = output.flush();
+}
+
+fn info_line(state: &GameState, info: &SearchInfo, hashfull: usize) -> String {
+    let score = match mate_distance(info.score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    let pv: Vec<String> = {
+        let mut state = *state;
+        info.principal_variation_moves
+            .iter()
+            .map(|action| {
+                let text = to_uci(&state, action);
+                state = action.apply(&state);
+                text
+            })
+            .collect()
+    };
+    format!(
+        "info depth {} multipv {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+        info.depth,
+        info.line,
+        score,
+        info.nodes,
+        info.nodes_per_second,
+        info.elapsed.as_millis(),
+        hashfull,
+        pv.join(" "),
+    )
+}
@@ -23,6 +23,7 @@ use crate::actions::{
     Castle,
     CastleDirection::{Kingside, Queenside},
     EnPassant,
+    ChessMove,
 };
 
 use std::cmp::{min, max};
@@ -134,6 +135,27 @@ pub fn legal_actions(state: &GameState) -> Vec<Box<dyn Action>> {
     results
 }
 
+pub fn legal_chess_moves(state: &GameState) -> Vec<ChessMove> {
+    let mut results: Vec<ChessMove> = vec![];
+
+    for action in legal_moves(state) {
+        results.push(ChessMove::Move(action));
+    }
+    for action in legal_captures(state) {
+        results.push(ChessMove::Capture(action));
+    }
+    for action in legal_en_passants(state) {
+        results.push(ChessMove::EnPassant(action));
+    }
+    for action in legal_castles(state) {
+        results.push(ChessMove::Castle(action));
+    }
+    for action in legal_promotions(state) {
+        results.push(ChessMove::Promotion(action));
+    }
+    results
+}
+
 // Determine the horizontal distance between two squares
 pub fn position_delta(from: usize, to: usize) -> (i32, i32) {
     let x = (to as i32 % 8) - (from as i32 % 8);
@@ -0,0 +1,151 @@
+// The single responsibility of this module is to determine whether a
+// GameState describes a position that could exist in a real game of chess.
+
+use crate::gamestate::GameState;
+
+use crate::pieces::{
+    PieceName::{Pawn, Rook, King},
+    Color,
+    Color::{White, Black},
+};
+
+use crate::actions::CastleDirection::{self, Kingside, Queenside};
+
+use crate::utilities::{
+    color_is_checked,
+    piece_is,
+};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy)]
+#[derive(Clone)]
+pub enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    TooManyPawns(Color),
+    TooManyPieces(Color),
+    PawnOnBackRank(usize),
+    CastlingWithoutKing(Color, CastleDirection),
+    CastlingWithoutRook(Color, CastleDirection),
+    InvalidEnPassantSquare(usize),
+    OpponentInCheck(Color),
+}
+
+impl GameState {
+    // Collect every reason this state could not have arisen in a game
+    pub fn validate(&self) -> Result<(), Vec<PositionError>> {
+        let mut errors = vec![];
+
+        for color in [White, Black].iter() {
+            validate_material(*color, self, &mut errors);
+            validate_castling_rights(*color, self, &mut errors);
+        }
+
+        // Pawns can never stand on the first or eighth rank
+        for square in (0..8).chain(56..64) {
+            if let Some(piece) = self.squares[square] {
+                if piece.name == Pawn {
+                    errors.push(PositionError::PawnOnBackRank(square));
+                }
+            }
+        }
+
+        if let Some(square) = self.en_passant_square {
+            if !en_passant_square_is_valid(square, self) {
+                errors.push(PositionError::InvalidEnPassantSquare(square));
+            }
+        }
+
+        // The player who just moved can't have left their own king in check
+        let waiting = if self.to_move == White { Black } else { White };
+        if color_is_checked(waiting, self) {
+            errors.push(PositionError::OpponentInCheck(waiting));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+}
+
+fn validate_material(color: Color, state: &GameState, errors: &mut Vec<PositionError>) {
+    let mut kings = 0;
+    let mut pawns = 0;
+    let mut pieces = 0;
+
+    for piece in state.squares.iter().flatten() {
+        if piece.color != color {
+            continue;
+        }
+        pieces += 1;
+        match piece.name {
+            King => kings += 1,
+            Pawn => pawns += 1,
+            _ => (),
+        }
+    }
+
+    if kings == 0 {
+        errors.push(PositionError::MissingKing(color));
+    }
+    if kings > 1 {
+        errors.push(PositionError::TooManyKings(color));
+    }
+    if pawns > 8 {
+        errors.push(PositionError::TooManyPawns(color));
+    }
+    if pieces > 16 {
+        errors.push(PositionError::TooManyPieces(color));
+    }
+}
+
+fn validate_castling_rights(color: Color, state: &GameState, errors: &mut Vec<PositionError>) {
+    let (kingside, queenside, king_square, back_rank) = match color {
+        White => (state.white_can_castle_kingside, state.white_can_castle_queenside, 4, 0),
+        Black => (state.black_can_castle_kingside, state.black_can_castle_queenside, 60, 56),
+    };
+
+    let rights: [(bool, CastleDirection, usize); 2] = [
+        (kingside, Kingside, back_rank + 7),
+        (queenside, Queenside, back_rank),
+    ];
+
+    for (has_right, direction, rook_square) in rights.iter() {
+        if !has_right {
+            continue;
+        }
+        if !piece_is(color, King, king_square, state) {
+            errors.push(PositionError::CastlingWithoutKing(color, *direction));
+        }
+        if !piece_is(color, Rook, *rook_square, state) {
+            errors.push(PositionError::CastlingWithoutRook(color, *direction));
+        }
+    }
+}
+
+// An en-passant square must sit directly behind a pawn that could have
+// just advanced two squares, with the square that pawn left still empty.
+fn en_passant_square_is_valid(square: usize, state: &GameState) -> bool {
+    if square >= 64 || state.squares[square].is_some() {
+        return false;
+    }
+    match state.to_move {
+        White => {
+            (40..48).contains(&square)
+                && piece_is(Black, Pawn, square - 8, state)
+                && state.squares[square + 8].is_none()
+        },
+        Black => {
+            (16..24).contains(&square)
+                && piece_is(White, Pawn, square + 8, state)
+                && state.squares[square - 8].is_none()
+        },
+    }
+}